@@ -6,8 +6,12 @@ use clap::{
 };
 
 use crate::{
-    commands,
-    config::{BranchName, CommitId, PatchName, PrNumber, Remote},
+    commands::{
+        self,
+        gen_patch::Commits,
+        pr_fetch::{self, PrFetchArg},
+    },
+    config::{BranchName, BranchRemote, CommitId, PatchName, PrNumber, Repository},
 };
 
 /// A tool which makes it easy to declaratively manage personal forks by automatically merging pull requests
@@ -36,6 +40,15 @@ pub enum Confirm {
     No,
 }
 
+/// Format of `patchy report`
+#[derive(ValueEnum, Clone, Debug, Copy)]
+pub enum ReportFormat {
+    /// Markdown, e.g. for a README or a GitHub release
+    Markdown,
+    /// HTML
+    Html,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Create example config file
@@ -49,41 +62,84 @@ pub enum Command {
         /// Do not ask for confirmation when overwriting the specified branch
         #[arg(short, long)]
         confirm: Option<Confirm>,
+        /// Use this profile of the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+        /// When a patch does not apply, even with a three-way merge, leave the conflict
+        /// for you to resolve and regenerate the `.patch` file from your resolution
+        #[arg(short, long)]
+        resolve_patches: bool,
+        /// Choose which pull requests, branches and patches to merge from a list,
+        /// and optionally save the choice to the config file
+        #[arg(short, long)]
+        interactive: bool,
     },
     /// Generate a .patch file from a commit hash
     GenPatch {
-        /// Transform this commit into a `.patch` file
-        commit: CommitId,
-        /// Choose a custom file name for the `.patch` file
+        /// Transform this commit into a `.patch` file, or a range of commits
+        /// such as `1a2b3c..4d5e6f` or `main..HEAD` into a numbered series of patches
+        #[arg(required_unless_present_any = ["last", "pr"])]
+        commits: Option<Commits>,
+        /// Transform the last N commits into a numbered series of patches
+        #[arg(short = 'n', long, value_name = "N", conflicts_with = "commits")]
+        last: Option<usize>,
+        /// Squash this pull request of `repo` into a single `.patch` file
+        #[arg(long, conflicts_with_all = ["commits", "last"])]
+        pr: Option<PrNumber>,
+        /// Use this commit of the pull request instead of its latest commit
+        #[arg(short = 'C', long, requires = "pr")]
+        commit: Option<CommitId>,
+        /// Choose a custom file name for the `.patch` file,
+        /// or for the directory of a series of patches
         #[arg(short, long)]
         filename: Option<PatchName>,
+        /// Add the patch or series to `patches` in the config
+        #[arg(short, long)]
+        add: bool,
+        /// Replace the pull request in `pull-requests` with the patch
+        #[arg(long, requires = "pr")]
+        replace: bool,
     },
     /// Fetch pull request for a GitHub repository as a local branch
     PrFetch {
-        /// Fetch PR of this number
-        pr: PrNumber,
-        /// The remote branch in the format `repo-owner/repo/branch`
+        /// Numbers of the pull requests to fetch, or their URLs
+        /// such as `https://github.com/helix-editor/helix/pull/12254`
         ///
-        /// The final part (`/branch`) is optional and defaults to `main`
+        /// Can be followed by the repository to fetch from, like `--repo`,
+        /// e.g. `patchy pr-fetch 12254 helix-editor/helix`
+        #[arg(required = true)]
+        prs: Vec<PrFetchArg>,
+        /// The repository in the format `repo-owner/repo` to fetch pull requests
+        /// that are given as numbers from
         ///
-        /// If omitted, uses the `origin` of the current repository
-        remote: Option<Remote>,
-        /// Choose a custom branch name for the fetched repo
+        /// If omitted, uses the `--remote-name` remote of the current repository
+        #[arg(short = 'R', long, conflicts_with = "remote_name")]
+        repo: Option<Repository>,
+        /// Remote of the current repository to fetch from, if `--repo` is omitted
+        #[arg(short, long, default_value = "origin")]
+        remote_name: String,
+        /// Choose a custom branch name for the fetched repo. Only for a single pull request
         #[arg(short, long)]
         branch: Option<BranchName>,
-        /// When fetching this PR, reset to this commit
+        /// When fetching this PR, reset to this commit. Only for a single pull request
         #[arg(short = 'C', long)]
         commit: Option<CommitId>,
-        /// Check out the first fetched pull request
+        /// Check out the first fetched pull request, or the `--combine` branch
         #[arg(short, long)]
         checkout: bool,
+        /// Create a branch of this name from the current branch, which merges all of the pull requests
+        #[arg(long)]
+        combine: Option<BranchName>,
     },
-    /// Fetch branch for a GitHub repository as a local branch
+    /// Fetch branch for a GitHub repository, or any git repository, as a local branch
     BranchFetch {
         /// The remote branch in the format `repo-owner/repo/branch`
         ///
-        /// The final part (`/branch`) is optional and defaults to `main`
-        remote: Remote,
+        /// The final part (`/branch`) is optional and defaults to the default branch of the repository
+        ///
+        /// Can also be any git URL or path, with an optional `#branch`.
+        /// If the branch is omitted, uses the default branch of the repository
+        remote: BranchRemote,
         /// When fetching this branch, reset to this commit
         #[arg(short = 'C', long)]
         commit: Option<CommitId>,
@@ -91,6 +147,69 @@ pub enum Command {
         #[arg(short, long)]
         checkout: bool,
     },
+    /// Add an entry to the config file
+    Add {
+        /// Entry to add
+        #[command(subcommand)]
+        entry: AddEntry,
+    },
+    /// Remove an entry from the config file
+    Remove {
+        /// Entry to remove
+        #[command(subcommand)]
+        entry: RemoveEntry,
+    },
+    /// List entries of the config file
+    List {
+        /// List the entries of this profile of the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Inspect the config file
+    Config {
+        /// What to do with the config file
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Maintain the patches of the config file
+    Patches {
+        /// What to do with the patches
+        #[command(subcommand)]
+        command: PatchesCommand,
+    },
+    /// Show how `local-branch` relates to the config and to upstream
+    Status {
+        /// Compare with this profile of the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Describe what `local-branch` contains, e.g. for a README or a GitHub release
+    Report {
+        /// Format of the report
+        #[arg(short, long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+        /// Describe the `local-branch` of this profile of the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Compare each pinned pull request, branch and `remote-branch` with the current head of its branch
+    Outdated {
+        /// Use this profile of the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Find the pull request, branch or patch which makes a command fail, e.g. `patchy bisect -- cargo test`
+    ///
+    /// Builds the fork with smaller and smaller subsets of the entries of the config, and reports
+    /// the entry, or the pair of entries, without which the command passes
+    Bisect {
+        /// The command to test each build with. It fails if it exits with a non-zero status
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+        /// Use this profile of the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
     /// Generate shell completions
     Completions {
         /// Shell to generate completions for
@@ -99,6 +218,86 @@ pub enum Command {
     },
 }
 
+/// Entry to add to the config file
+#[derive(Subcommand, Debug)]
+pub enum AddEntry {
+    /// Add a pull request to `pull-requests`
+    Pr {
+        /// Number of the pull request
+        pr: PrNumber,
+        /// Pin the pull request to its current latest commit
+        #[arg(long)]
+        pin: bool,
+        /// Why this pull request is carried
+        #[arg(short, long)]
+        note: Option<String>,
+    },
+    /// Add a branch to `branches`
+    Branch {
+        /// The remote branch in the format `repo-owner/repo/branch`
+        ///
+        /// The final part (`/branch`) is optional and defaults to the default branch of the repository
+        ///
+        /// Can also be any git URL or path, with an optional `#branch`
+        remote: BranchRemote,
+        /// Pin the branch to its current latest commit
+        #[arg(long)]
+        pin: bool,
+        /// Why this branch is carried
+        #[arg(short, long)]
+        note: Option<String>,
+    },
+    /// Add a patch to `patches`
+    Patch {
+        /// Name of the patch, e.g. `remove-tab` for `.patchy/remove-tab.patch`
+        name: PatchName,
+    },
+}
+
+/// Entry to remove from the config file
+#[derive(Subcommand, Debug)]
+pub enum RemoveEntry {
+    /// Remove a pull request from `pull-requests`
+    Pr {
+        /// Number of the pull request
+        pr: PrNumber,
+    },
+    /// Remove a branch from `branches`
+    Branch {
+        /// The remote branch in the format `repo-owner/repo/branch`, or a git URL
+        remote: BranchRemote,
+    },
+    /// Remove a patch from `patches`
+    Patch {
+        /// Name of the patch
+        name: PatchName,
+    },
+}
+
+/// Subcommands of `patchy config`
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum ConfigCommand {
+    /// Print the config file
+    Show {
+        /// Print the effective config after merging the included files and the
+        /// user's global config, and where each value came from
+        #[arg(long)]
+        resolved: bool,
+    },
+}
+
+/// Subcommands of `patchy patches`
+#[derive(Subcommand, Debug, Clone)]
+pub enum PatchesCommand {
+    /// Apply each patch onto the current base with its pull requests and branches,
+    /// rewrite the patches whose context changed and report the obsolete ones
+    Refresh {
+        /// Use this profile of the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+}
+
 impl Command {
     /// Execute the command
     pub async fn execute(self, use_gh_cli: bool) -> anyhow::Result<()> {
@@ -106,22 +305,76 @@ impl Command {
             Self::Init {
                 confirm: overwrite_file_if_exists,
             } => commands::init(overwrite_file_if_exists)?,
-            Self::Run { confirm } => commands::run(confirm, use_gh_cli).await?,
-            Self::GenPatch { commit, filename } => {
-                commands::gen_patch(commit, filename)?;
+            Self::Run {
+                confirm,
+                profile,
+                resolve_patches,
+                interactive,
+            } => commands::run(confirm, profile, resolve_patches, interactive, use_gh_cli).await?,
+            Self::GenPatch {
+                pr: Some(pr),
+                commit,
+                filename,
+                add,
+                replace,
+                ..
+            } => {
+                commands::gen_patch::gen_patch_from_pr(
+                    pr, commit, filename, add, replace, use_gh_cli,
+                )
+                .await?;
+            }
+            Self::GenPatch {
+                commits,
+                last,
+                filename,
+                add,
+                ..
+            } => {
+                commands::gen_patch(commits, last, filename, add)?;
             }
             Self::PrFetch {
-                pr,
-                remote,
+                prs,
+                repo,
+                remote_name,
                 branch,
                 commit,
                 checkout,
-            } => commands::pr_fetch(pr, remote, branch, commit, checkout, use_gh_cli).await?,
+                combine,
+            } => {
+                let (prs, repo) = pr_fetch::split_args(prs, repo)?;
+                commands::pr_fetch(
+                    prs,
+                    repo,
+                    &remote_name,
+                    branch,
+                    commit,
+                    checkout,
+                    combine,
+                    use_gh_cli,
+                )
+                .await?;
+            }
             Self::BranchFetch {
                 remote,
                 commit,
                 checkout,
             } => commands::branch_fetch(remote, commit, checkout, use_gh_cli).await?,
+            Self::Add { entry } => commands::add(entry, use_gh_cli).await?,
+            Self::Remove { entry } => commands::remove(entry, use_gh_cli).await?,
+            Self::List { profile } => commands::list(profile.as_deref())?,
+            Self::Config {
+                command: ConfigCommand::Show { resolved },
+            } => commands::config_show(resolved)?,
+            Self::Patches {
+                command: PatchesCommand::Refresh { profile },
+            } => commands::patches_refresh(profile, use_gh_cli).await?,
+            Self::Status { profile } => commands::status(profile, use_gh_cli).await?,
+            Self::Report { format, profile } => commands::report(format, profile.as_deref())?,
+            Self::Outdated { profile } => commands::outdated(profile, use_gh_cli).await?,
+            Self::Bisect { command, profile } => {
+                commands::bisect(command, profile, use_gh_cli).await?;
+            }
             Self::Completions { shell } => {
                 shell.generate(&mut Cli::command(), &mut std::io::stdout());
             }
@@ -141,22 +394,9 @@ const STYLES: clap::builder::Styles = clap::builder::Styles::styled()
     .valid(AnsiColor::BrightCyan.on_default().effects(Effects::BOLD))
     .invalid(AnsiColor::BrightYellow.on_default().effects(Effects::BOLD));
 
-/// Represents a single branch
-#[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Branch {
-    /// Name of the GitHub owner of the repository
-    pub repo_owner: String,
-    /// Name of the repository this branch belongs to
-    pub repo_name: String,
-    /// Name of this branch in the remote
-    pub name: String,
-    /// When fetching this PR, reset to this commit
-    pub commit: Option<CommitId>,
-}
-
 #[cfg(test)]
 mod test {
-    use crate::config::{RepoName, RepoOwner};
+    use crate::config::{Remote, RepoName, RepoOwner};
 
     use super::*;
 
@@ -167,7 +407,7 @@ mod test {
             Remote {
                 owner: RepoOwner::try_new("helix-editor").unwrap(),
                 repo: RepoName::try_new("helix").unwrap(),
-                branch: BranchName::try_new("main").unwrap(),
+                branch: None,
                 commit: None
             }
         );
@@ -178,7 +418,7 @@ mod test {
             Remote {
                 owner: RepoOwner::try_new("helix-editor").unwrap(),
                 repo: RepoName::try_new("helix").unwrap(),
-                branch: BranchName::try_new("master").unwrap(),
+                branch: Some(BranchName::try_new("master").unwrap()),
                 commit: Some(CommitId::try_new("1a2b3c").unwrap())
             }
         );
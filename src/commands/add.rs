@@ -0,0 +1,107 @@
+//! `add` subcommand
+
+use anyhow::bail;
+use colored::Colorize as _;
+use itertools::Itertools as _;
+
+use crate::cli::AddEntry;
+use crate::config::{self, BranchEntry, Config, EntryOptions, PullRequest, edit::ConfigFile};
+use crate::utils::format_pr;
+use crate::{github, patch};
+
+/// Validate the `entry` and add it to the config file
+pub async fn add(entry: AddEntry, use_gh_cli: bool) -> anyhow::Result<()> {
+    let config = Config::read()?;
+    let mut file = ConfigFile::open()?;
+    let use_gh_cli = use_gh_cli || config.use_gh_cli;
+
+    match entry {
+        AddEntry::Pr { pr, pin, note } => {
+            let response = github::fetch_pr_data(&config.repo, pr, use_gh_cli).await?;
+
+            let pull_request = PullRequest {
+                number: pr,
+                commit: pin.then(|| response.head.sha.clone()),
+                options: EntryOptions {
+                    note,
+                    ..EntryOptions::default()
+                },
+                after: Vec::new(),
+            };
+
+            file.add_pull_request(&pull_request)?;
+            file.save()?;
+
+            log::info!(
+                "Added pull request {}{}",
+                format_pr(pr, &response.title, &response.html_url),
+                pin_message(pull_request.commit.as_ref())
+            );
+        }
+        AddEntry::Branch {
+            mut remote,
+            pin,
+            note,
+        } => {
+            // this also checks that the branch exists
+            let head = github::fetch_branch_remote_head(&remote, use_gh_cli).await?;
+
+            if pin {
+                remote.set_commit(Some(head));
+            }
+
+            let branch = BranchEntry {
+                remote,
+                options: EntryOptions {
+                    note,
+                    ..EntryOptions::default()
+                },
+            };
+
+            // `owner/repo` and `owner/repo/master` are the same branch if `master` is the default
+            let default_branch =
+                github::fetch_branch_remote_default_branch(&branch.remote, use_gh_cli)
+                    .await
+                    .inspect_err(|err| log::debug!("failed to find the default branch: {err}"))
+                    .ok();
+
+            file.add_branch(&branch, default_branch.as_ref())?;
+            file.save()?;
+
+            log::info!(
+                "Added branch {}{}",
+                branch.remote.to_string().bright_blue(),
+                pin_message(branch.remote.commit())
+            );
+        }
+        AddEntry::Patch { name } => {
+            if patch::files(&name)?.is_none() {
+                let path = config::PATH.join(name.as_ref());
+                bail!(
+                    "patch {name} does not exist: there is no file {} with one of the extensions ({}), \
+                     and no directory {} with a series of patches. Create it with `patchy gen-patch`",
+                    path.display(),
+                    patch::EXTENSIONS
+                        .iter()
+                        .map(|extension| format!(".{extension}"))
+                        .join(", "),
+                    path.display()
+                );
+            }
+
+            file.add_patch(&name)?;
+            file.save()?;
+
+            log::info!("Added patch {}", name.to_string().bright_blue());
+        }
+    }
+
+    Ok(())
+}
+
+/// Describe the commit an entry is pinned to
+fn pin_message(commit: Option<&config::CommitId>) -> String {
+    commit
+        .map(|commit| format!(", pinned at commit {}", commit.as_ref().bright_yellow()))
+        .unwrap_or_default()
+}
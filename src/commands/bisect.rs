@@ -0,0 +1,332 @@
+//! `bisect` subcommand
+
+use std::fmt::Display;
+use std::process::Command;
+
+use anyhow::{Result, anyhow, bail};
+use colored::Colorize as _;
+use indexmap::IndexSet;
+
+use crate::commands::run::{Build, build};
+use crate::config::{self, BranchEntry, BranchName, Config, Hooks, Patch, PullRequest};
+use crate::git;
+use crate::lock::{Lockfile, Outcome};
+
+/// An entry of the config which can be left out of the build
+#[derive(Debug, Clone)]
+enum Entry {
+    /// A pull request, pinned to the commit which was merged
+    PullRequest(PullRequest),
+    /// A branch
+    Branch(BranchEntry),
+    /// A patch
+    Patch(Patch),
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PullRequest(pull_request) => write!(f, "pull request #{}", pull_request.number),
+            Self::Branch(branch) => write!(f, "branch {}", branch.remote.name()),
+            Self::Patch(patch) => write!(f, "patch {patch}"),
+        }
+    }
+}
+
+/// Entries which make the command fail, found by [`search`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Culprit {
+    /// The command fails with this entry on its own
+    Single(usize),
+    /// The command fails only with both entries. The first one is merged before the second one
+    Pair(usize, usize),
+}
+
+/// Build the fork with smaller and smaller subsets of the entries of the config, and report
+/// the entry or the pair of entries which make the `command` fail
+///
+/// If `profile` is given, that profile of the config is used
+pub async fn bisect(command: Vec<String>, profile: Option<String>, use_gh_cli: bool) -> Result<()> {
+    let mut config = Config::read_with_profile(profile.as_deref())?;
+
+    let use_gh_cli = use_gh_cli || config.use_gh_cli;
+
+    // hooks could drop entries, which would change the subsets behind our back
+    config.hooks = Hooks::default();
+
+    let previous_branch = git::get_head_commit()?;
+    let command_line = command.join(" ");
+
+    log::info!("Building the fork with every entry");
+
+    let (passed, lockfile) = test(&config, &command, &previous_branch, use_gh_cli).await?;
+
+    if passed {
+        log::info!(
+            "{} passes with every entry, there is nothing to bisect",
+            command_line.bright_cyan()
+        );
+        return Ok(());
+    }
+
+    let entries = entries(&config, &lockfile);
+
+    if entries.is_empty() {
+        bail!(
+            "{command_line} fails, but no entry was merged, so the base is broken. \
+             There is nothing to bisect"
+        );
+    }
+
+    // every build uses the same base, and the same commit of each pull request
+    config.remote_branch = Some(config::Branch {
+        name: BranchName::try_new(lockfile.base.branch.clone())
+            .map_err(|err| anyhow!("invalid branch in the lockfile: {err}"))?,
+        commit: lockfile.base.commit.clone(),
+    });
+    config.pull_request_queries.clear();
+
+    log::info!(
+        "{} fails with these {} entries, building the base without them",
+        command_line.bright_cyan(),
+        entries.len()
+    );
+
+    if !test(
+        &select(&config, &entries, &[]),
+        &command,
+        &previous_branch,
+        use_gh_cli,
+    )
+    .await?
+    .0
+    {
+        bail!(
+            "{command_line} fails without any of the entries, so the base is broken. \
+             There is nothing to bisect"
+        );
+    }
+
+    let culprit = search(entries.len(), async |indices: &[usize]| {
+        log::info!(
+            "Building the fork with {}",
+            indices
+                .iter()
+                .filter_map(|index| entries.get(*index))
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let config = select(&config, &entries, indices);
+        test(&config, &command, &previous_branch, use_gh_cli)
+            .await
+            .map(|(passed, _)| !passed)
+    })
+    .await?;
+
+    let entry = |index: usize| {
+        entries
+            .get(index)
+            .expect("`search` returns indices of `entries`")
+            .to_string()
+            .bright_yellow()
+    };
+
+    match culprit {
+        Culprit::Single(index) => log::error!(
+            "{} fails because of {}",
+            command_line.bright_cyan(),
+            entry(index)
+        ),
+        Culprit::Pair(first, second) => log::error!(
+            "{} fails when {} is merged together with {}, but passes with {} on its own",
+            command_line.bright_cyan(),
+            entry(second),
+            entry(first),
+            entry(second)
+        ),
+    }
+
+    Ok(())
+}
+
+/// Build the fork of the `config`, and run the `command` in it
+///
+/// Returns if the command passed, and what the build merged. Afterwards,
+/// the `previous_branch` is checked out again and the build is deleted
+async fn test(
+    config: &Config,
+    command: &[String],
+    previous_branch: &str,
+    use_gh_cli: bool,
+) -> Result<(bool, Lockfile)> {
+    let Build {
+        branch, lockfile, ..
+    } = build(config, use_gh_cli, false).await?;
+
+    let [program, args @ ..] = command else {
+        bail!("missing the command to test");
+    };
+
+    log::info!("Running {}", command.join(" ").bright_cyan());
+
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(&*git::ROOT)
+        .status();
+
+    git::checkout(previous_branch)?;
+    git::delete_branch(
+        &BranchName::try_new(branch).map_err(|err| anyhow!("invalid branch: {err}"))?,
+    )?;
+
+    let passed = status
+        .map_err(|err| anyhow!("failed to run {program}: {err}"))?
+        .success();
+
+    log::info!(
+        "{}",
+        if passed {
+            "passed".bright_green()
+        } else {
+            "failed".bright_red()
+        }
+    );
+
+    Ok((passed, lockfile))
+}
+
+/// Entries of the `config` which the build in the `lockfile` merged, in the order they were merged
+fn entries(config: &Config, lockfile: &Lockfile) -> Vec<Entry> {
+    let pull_requests = lockfile
+        .pull_requests
+        .iter()
+        .filter(|record| record.outcome == Outcome::Merged)
+        .map(|record| {
+            let pull_request = config
+                .pull_requests
+                .iter()
+                .find(|pull_request| pull_request.number == record.number)
+                .cloned()
+                .unwrap_or_else(|| PullRequest {
+                    number: record.number,
+                    commit: None,
+                    options: config::EntryOptions::default(),
+                    after: Vec::new(),
+                });
+
+            Entry::PullRequest(PullRequest {
+                commit: record.head.clone().or(pull_request.commit),
+                ..pull_request
+            })
+        });
+
+    // a record is pushed for each branch and patch, in the order of the config
+    let branches = config
+        .branches
+        .iter()
+        .zip(&lockfile.branches)
+        .filter(|(_, record)| record.outcome == Outcome::Merged)
+        .map(|(branch, _)| Entry::Branch(branch.clone()));
+    let patches = config
+        .patches
+        .iter()
+        .zip(&lockfile.patches)
+        .filter(|(_, record)| record.outcome == Outcome::Merged)
+        .map(|(patch, _)| Entry::Patch(patch.clone()));
+
+    pull_requests.chain(branches).chain(patches).collect()
+}
+
+/// The `config` with only the `entries` at the `indices`
+fn select(config: &Config, entries: &[Entry], indices: &[usize]) -> Config {
+    let mut config = Config {
+        pull_requests: Vec::new(),
+        branches: Vec::new(),
+        patches: IndexSet::new(),
+        ..config.clone()
+    };
+
+    for entry in indices.iter().filter_map(|index| entries.get(*index)) {
+        match entry.clone() {
+            Entry::PullRequest(pull_request) => config.pull_requests.push(pull_request),
+            Entry::Branch(branch) => config.branches.push(branch),
+            Entry::Patch(patch) => {
+                config.patches.insert(patch);
+            }
+        }
+    }
+
+    config
+}
+
+/// Find the entries which make a build fail, given that it fails with all `len` entries
+/// and passes with none of them
+///
+/// `fails` builds with the entries at the given indices, and returns `true` if it fails
+///
+/// First finds the shortest prefix of the entries which fails, whose last entry is the culprit.
+/// If the culprit passes on its own, finds the shortest prefix which fails together with it
+///
+/// Fails if there are no entries, as then the base itself is broken
+async fn search(
+    len: usize,
+    mut fails: impl AsyncFnMut(&[usize]) -> Result<bool>,
+) -> Result<Culprit> {
+    if len == 0 {
+        bail!("there are no entries to search");
+    }
+
+    // the prefix of `passing` entries passes, and the prefix of `failing` entries fails
+    let (mut passing, mut failing) = (0, len);
+    while failing - passing > 1 {
+        let middle = usize::midpoint(passing, failing);
+        if fails(&(0..middle).collect::<Vec<_>>()).await? {
+            failing = middle;
+        } else {
+            passing = middle;
+        }
+    }
+    let culprit = failing - 1;
+
+    if culprit == 0 || fails(&[culprit]).await? {
+        return Ok(Culprit::Single(culprit));
+    }
+
+    let (mut passing, mut failing) = (0, culprit);
+    while failing - passing > 1 {
+        let middle = usize::midpoint(passing, failing);
+        if fails(&(0..middle).chain([culprit]).collect::<Vec<_>>()).await? {
+            failing = middle;
+        } else {
+            passing = middle;
+        }
+    }
+
+    Ok(Culprit::Pair(failing - 1, culprit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Search for the culprit when builds fail if they contain all of the `culprits`
+    async fn search_for(len: usize, culprits: &[usize]) -> Result<Culprit> {
+        search(len, async |indices: &[usize]| {
+            Ok(culprits.iter().all(|culprit| indices.contains(culprit)))
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn search_culprit() {
+        assert_eq!(search_for(1, &[0]).await.unwrap(), Culprit::Single(0));
+        assert_eq!(search_for(10, &[0]).await.unwrap(), Culprit::Single(0));
+        assert_eq!(search_for(10, &[6]).await.unwrap(), Culprit::Single(6));
+        assert_eq!(search_for(10, &[9]).await.unwrap(), Culprit::Single(9));
+        assert_eq!(search_for(10, &[2, 7]).await.unwrap(), Culprit::Pair(2, 7));
+        assert_eq!(search_for(10, &[0, 1]).await.unwrap(), Culprit::Pair(0, 1));
+        assert_eq!(search_for(10, &[8, 9]).await.unwrap(), Culprit::Pair(8, 9));
+        search_for(0, &[]).await.unwrap_err();
+    }
+}
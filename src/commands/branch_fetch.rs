@@ -2,25 +2,27 @@
 
 use colored::Colorize as _;
 
-use crate::config::{CommitId, Remote};
+use crate::config::{BranchRemote, CommitId};
 use crate::git;
 use crate::github;
 use anyhow::anyhow;
 
 /// Fetch the given branch
 pub async fn branch_fetch(
-    remote: Remote,
+    mut remote: BranchRemote,
     commit: Option<CommitId>,
     checkout: bool,
     use_gh_cli: bool,
 ) -> anyhow::Result<()> {
-    let (_, info) = github::fetch_branch(&remote, use_gh_cli).await?;
+    if commit.is_some() {
+        remote.set_commit(commit.clone());
+    }
+
+    let info = github::fetch_branch_remote(&remote, use_gh_cli).await?;
 
     log::info!(
-        "Fetched branch {}/{}/{} available at branch {}{}",
-        remote.owner,
-        remote.repo,
-        info.branch.upstream_branch_name,
+        "Fetched branch {} available at branch {}{}",
+        remote.name(),
         info.branch.local_branch_name.as_ref().bright_cyan(),
         commit
             .map(|commit_hash| { format!(", at commit {}", commit_hash.as_ref().bright_yellow()) })
@@ -0,0 +1,23 @@
+//! `config show` subcommand
+
+use crate::config::{self, layer::Resolved};
+
+/// Print the config file
+///
+/// If `resolved`, print the config after merging all of its layers instead,
+/// with the file each value comes from
+#[expect(
+    clippy::print_stdout,
+    reason = "the config is the output of the command"
+)]
+pub fn config_show(resolved: bool) -> anyhow::Result<()> {
+    let contents = config::read_file()?;
+
+    if resolved {
+        print!("{}", Resolved::new(&contents)?);
+    } else {
+        print!("{contents}");
+    }
+
+    Ok(())
+}
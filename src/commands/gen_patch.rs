@@ -2,15 +2,72 @@
 
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 
-use crate::config::{self, CommitId, PatchName};
-use crate::git;
-use crate::utils::normalize_commit_msg;
+use crate::commands::pr_fetch::default_repo;
+use crate::config::{self, BranchName, CommitId, Config, PatchName, PrNumber, edit::ConfigFile};
+use crate::utils::{format_pr, normalize_commit_msg, with_uuid};
+use crate::{git, github, patch};
 
-/// Generate patch `filename` at the given `Commit`
-pub fn gen_patch(commit: CommitId, filename: Option<PatchName>) -> anyhow::Result<()> {
+/// Commits to turn into patches, e.g. `1a2b3c`, `HEAD~3..HEAD` or `main..feature`
+///
+/// Each side is any revision that git understands, resolved with `git rev-parse`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Commits {
+    /// A single commit, which becomes a single `.patch` file
+    Single(String),
+    /// The commits after `from`, up to and including `to`, which become a series of patches
+    Range {
+        /// Commit before the first commit of the series
+        from: String,
+        /// Last commit of the series
+        to: String,
+    },
+}
+
+impl FromStr for Commits {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let revision = |revision: &str| {
+            if revision.is_empty() || revision.starts_with('.') {
+                bail!("invalid range of commits `{s}`, expected e.g. `HEAD~3..HEAD`");
+            }
+            Ok(revision.to_string())
+        };
+
+        match s.split_once("..") {
+            Some((from, to)) => Ok(Self::Range {
+                from: revision(from)?,
+                to: revision(to)?,
+            }),
+            None => revision(s).map(Self::Single),
+        }
+    }
+}
+
+/// The commit that `revision` points to, e.g. `HEAD~3`
+fn resolve(revision: &str) -> anyhow::Result<CommitId> {
+    let commit =
+        git::rev_parse(revision).map_err(|err| anyhow!("`{revision}` is not a commit:\n{err}"))?;
+
+    CommitId::try_new(commit).map_err(|err| anyhow!("{err}"))
+}
+
+/// Generate patches from `commits`, or from the `last` commits
+///
+/// A single commit becomes the patch `filename`. Multiple commits become a numbered series
+/// of patches in the directory `filename`, which can be used as a single entry of `patches`.
+///
+/// If `add`, the patch is also added to `patches` in the config
+pub fn gen_patch(
+    commits: Option<Commits>,
+    last: Option<usize>,
+    filename: Option<PatchName>,
+    add: bool,
+) -> anyhow::Result<()> {
     if !config::PATH.exists() {
         log::info!(
             "Config directory {} does not exist, creating it...",
@@ -19,22 +76,151 @@ pub fn gen_patch(commit: CommitId, filename: Option<PatchName>) -> anyhow::Resul
         fs::create_dir_all(&*config::PATH)?;
     }
 
+    let patch_filename = match (commits, last) {
+        (Some(Commits::Single(commit)), _) => gen_single_patch(resolve(&commit)?, filename)?,
+        (Some(Commits::Range { from, to }), _) => {
+            let (from, to) = (resolve(&from)?, resolve(&to)?);
+            let patch_filename = filename.unwrap_or_else(|| patch_name_of(to.as_ref()));
+            let dir = series_dir(&patch_filename)?;
+
+            git::save_range_as_patches(&from, &to, &dir)
+                .map_err(|err| anyhow!("failed to get patch output for {from}..{to}\n{err}"))
+                .and_then(|output| log_series(&output, &dir))?;
+
+            patch_filename
+        }
+        (None, Some(0)) => bail!("`--last` must be at least 1"),
+        (None, Some(count)) => {
+            let patch_filename = filename.unwrap_or_else(|| patch_name_of("HEAD"));
+            let dir = series_dir(&patch_filename)?;
+
+            git::save_last_commits_as_patches(count, &dir)
+                .map_err(|err| {
+                    anyhow!("failed to get patch output for the last {count} commits\n{err}")
+                })
+                .and_then(|output| log_series(&output, &dir))?;
+
+            patch_filename
+        }
+        (None, None) => bail!("expected a commit, a range of commits or `--last`"),
+    };
+
+    if add {
+        let mut file = ConfigFile::open()?;
+        file.add_patch(&patch_filename)?;
+        file.save()?;
+
+        log::info!("Added patch {patch_filename} to `patches`");
+    }
+
+    Ok(())
+}
+
+/// Generate a patch which squashes the pull request `pr` into a single commit
+///
+/// The patch is authored by the author of the pull request, with its title as the subject
+/// and its URL in the message. If `commit` is given, uses that commit of the pull request.
+///
+/// If `add`, the patch is also added to `patches` in the config. If `replace`, the pull request
+/// is removed from `pull-requests` as well
+pub async fn gen_patch_from_pr(
+    pr: PrNumber,
+    commit: Option<CommitId>,
+    filename: Option<PatchName>,
+    add: bool,
+    replace: bool,
+    use_gh_cli: bool,
+) -> anyhow::Result<()> {
+    // without a config, the pull request is looked up in the repository of `origin`
+    let config = if config::FILE_PATH.exists() {
+        Some(Config::read()?)
+    } else {
+        None
+    };
+    let use_gh_cli = use_gh_cli || config.as_ref().is_some_and(|config| config.use_gh_cli);
+    if let Some(config) = &config {
+        git::configure_commits(&config.committer);
+    }
+    let repo = match config {
+        Some(config) if !config.repo.is_empty() => config.repo,
+        _ => default_repo(None, "origin")?,
+    };
+
+    let response = github::fetch_pr_data(&repo, pr, use_gh_cli).await?;
+    let info = github::fetch_pull_request_branch(&response, pr, None, commit.as_ref())?;
+    // Attempt to cleanup after ourselves
+    let _ = git::remove_remote(&info.remote.local_remote_alias);
+
+    let head = info.branch.local_branch_name;
+    let base = BranchName::try_new(with_uuid(response.base.r#ref.as_ref()))
+        .expect("adding UUID to branch name does not invalidate it");
+
+    let squashed =
+        git::fetch_remote_branch(&base, &response.base.r#ref, &response.base.repo.clone_url)
+            .map_err(|err| {
+                anyhow!(
+                    "failed to fetch the base branch {} of #{pr}:\n{err}",
+                    response.base.r#ref
+                )
+            })
+            .and_then(|_| git::merge_base(base.as_ref(), head.as_ref()))
+            .and_then(|merge_base| {
+                git::commit_tree(
+                    head.as_ref(),
+                    &merge_base,
+                    &format!("{}\n\nPull request: {}", response.title, response.html_url),
+                    &response.user.login,
+                    &response.user.noreply_email(),
+                )
+            });
+
+    let _ = git::delete_branch(&base);
+    let _ = git::delete_branch(&head);
+
+    let squashed = CommitId::try_new(squashed?).map_err(|err| anyhow!("{err}"))?;
+
+    if !config::PATH.exists() {
+        fs::create_dir_all(&*config::PATH)?;
+    }
+
+    let filename = filename.unwrap_or_else(|| {
+        PatchName::try_new(PathBuf::from(normalize_commit_msg(&response.title)))
+            .expect("normalized title is not empty")
+    });
+    let patch_filename = gen_single_patch(squashed, Some(filename))?;
+
+    log::info!(
+        "Squashed {} into patch {patch_filename}",
+        format_pr(pr, &response.title, &response.html_url)
+    );
+
+    if add || replace {
+        let mut file = ConfigFile::open()?;
+
+        if replace {
+            match file.remove_pull_request(pr) {
+                Ok(()) => log::info!("Removed pull request #{pr} from `pull-requests`"),
+                Err(err) => log::warn!("{err}"),
+            }
+        }
+
+        file.add_patch(&patch_filename)?;
+        file.save()?;
+
+        log::info!("Added patch {patch_filename} to `patches`");
+    }
+
+    Ok(())
+}
+
+/// Generate patch `filename` at the given `commit`
+fn gen_single_patch(commit: CommitId, filename: Option<PatchName>) -> anyhow::Result<PatchName> {
     // 1. if the user provides a custom filename for the patch file, use that
     // 2. otherwise use the commit message
     // 3. if all fails use the commit hash
-    let patch_filename = filename.unwrap_or_else(|| {
-        git::get_message_of_commit(commit.as_ref()).map_or_else(
-            |_| {
-                PatchName::try_new(commit.clone().into_inner().into()).expect("commit is not empty")
-            },
-            |commit_msg| {
-                PatchName::try_new(PathBuf::from(normalize_commit_msg(&commit_msg)))
-                    .expect("normalized commit message is not empty")
-            },
-        )
-    });
+    let patch_filename = filename.unwrap_or_else(|| patch_name_of(commit.as_ref()));
 
-    let patch_file_path = config::PATH.join(format!("{patch_filename}.patch"));
+    let patch_file_path = patch::file_of(&patch_filename);
 
     // Paths are UTF-8 encoded. If we cannot convert to UTF-8 that means it is not a
     // valid path
@@ -54,5 +240,74 @@ pub fn gen_patch(commit: CommitId, filename: Option<PatchName>) -> anyhow::Resul
         patch_file_path.to_string_lossy()
     );
 
+    Ok(patch_filename)
+}
+
+/// Name of a patch made from the `commit`, derived from its message
+fn patch_name_of(commit: &str) -> PatchName {
+    git::get_message_of_commit(commit).map_or_else(
+        |_| PatchName::try_new(PathBuf::from(commit)).expect("commit is not empty"),
+        |commit_msg| {
+            PatchName::try_new(PathBuf::from(normalize_commit_msg(&commit_msg)))
+                .expect("normalized commit message is not empty")
+        },
+    )
+}
+
+/// Path to the directory of the patch series `patch_filename`, which must not exist yet
+fn series_dir(patch_filename: &PatchName) -> anyhow::Result<String> {
+    let dir = config::PATH.join(patch_filename.as_ref());
+
+    if dir.exists() {
+        bail!(
+            "{} already exists, remove it or choose a different name with `--filename`",
+            dir.display()
+        );
+    }
+
+    dir.into_os_string()
+        .into_string()
+        .map_err(|dir| anyhow!("invalid path: {dir:?}"))
+}
+
+/// Log the patches of a series created by `git format-patch`, which printed `output`
+fn log_series(output: &str, dir: &str) -> anyhow::Result<()> {
+    let count = output.lines().filter(|line| !line.is_empty()).count();
+
+    if count == 0 {
+        bail!("there are no commits in the range");
+    }
+
+    log::info!("Created a series of {count} patches at {dir}");
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commits() {
+        assert_eq!(
+            "1a2b3c".parse::<Commits>().unwrap(),
+            Commits::Single("1a2b3c".to_string())
+        );
+        assert_eq!(
+            "1a2b3c..4d5e6f".parse::<Commits>().unwrap(),
+            Commits::Range {
+                from: "1a2b3c".to_string(),
+                to: "4d5e6f".to_string(),
+            }
+        );
+        assert_eq!(
+            "HEAD~3..main".parse::<Commits>().unwrap(),
+            Commits::Range {
+                from: "HEAD~3".to_string(),
+                to: "main".to_string(),
+            }
+        );
+        "1a2b3c..".parse::<Commits>().unwrap_err();
+        "1a2b3c...4d5e6f".parse::<Commits>().unwrap_err();
+    }
+}
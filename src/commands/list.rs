@@ -0,0 +1,85 @@
+//! `list` subcommand
+
+use colored::Colorize as _;
+
+use crate::config::{BranchEntry, Config, EntryOptions};
+use crate::utils::format_note;
+
+/// Print every entry of the config file, with the `profile` applied
+#[expect(clippy::print_stdout, reason = "the list is the output of the command")]
+pub fn list(profile: Option<&str>) -> anyhow::Result<()> {
+    let config = Config::read_with_profile(profile)?;
+
+    println!(
+        "{} {} {}",
+        config.repo.bright_blue(),
+        format!(
+            "({})",
+            config.remote_branch.as_ref().map_or_else(
+                || "default branch".to_string(),
+                |branch| branch.name.to_string()
+            )
+        )
+        .bright_black(),
+        format!("-> {}", config.local_branch).bright_cyan()
+    );
+
+    if !config.pull_requests.is_empty() {
+        println!("\n{}", "pull requests:".bright_green());
+    }
+    for pull_request in &config.pull_requests {
+        println!(
+            "  #{}{}",
+            pull_request.to_string().bright_blue(),
+            format_options(&pull_request.options)
+        );
+    }
+
+    if !config.branches.is_empty() {
+        println!("\n{}", "branches:".bright_green());
+    }
+    for BranchEntry { remote, options } in &config.branches {
+        println!(
+            "  {}{}",
+            remote.to_string().bright_blue(),
+            format_options(options)
+        );
+    }
+
+    if !config.patches.is_empty() {
+        println!("\n{}", "patches:".bright_green());
+    }
+    for patch in &config.patches {
+        println!(
+            "  {}{}{}",
+            patch.to_string().bright_blue(),
+            patch
+                .download
+                .as_ref()
+                .map(|download| format!(" {}", format!("<{}>", download.url).bright_black()))
+                .unwrap_or_default(),
+            if patch.enabled {
+                String::new()
+            } else {
+                " [disabled]".yellow().to_string()
+            }
+        );
+    }
+
+    Ok(())
+}
+
+/// Format the non-default `options` of an entry
+fn format_options(options: &EntryOptions) -> String {
+    let mut formatted = String::new();
+
+    if let Some(merge_strategy) = options.merge_strategy {
+        formatted.push_str(&format!(" [{}]", merge_strategy.as_str()).bright_black());
+    }
+    if !options.enabled {
+        formatted.push_str(&" [disabled]".yellow());
+    }
+    formatted.push_str(&format_note(options.note.as_deref()));
+
+    formatted
+}
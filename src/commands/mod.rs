@@ -1,13 +1,31 @@
 //! Commands for patchy
 
+pub mod add;
+pub mod bisect;
 pub mod branch_fetch;
+pub mod config_show;
 pub mod gen_patch;
 pub mod init;
+pub mod list;
+pub mod outdated;
+pub mod patches_refresh;
 pub mod pr_fetch;
+pub mod remove;
+pub mod report;
 pub mod run;
+pub mod status;
 
+pub use add::add;
+pub use bisect::bisect;
 pub use branch_fetch::branch_fetch;
+pub use config_show::config_show;
 pub use gen_patch::gen_patch;
 pub use init::init;
+pub use list::list;
+pub use outdated::outdated;
+pub use patches_refresh::patches_refresh;
 pub use pr_fetch::pr_fetch;
+pub use remove::remove;
+pub use report::report;
 pub use run::run;
+pub use status::status;
@@ -0,0 +1,346 @@
+//! `outdated` subcommand
+
+use colored::Colorize as _;
+
+use crate::commands::run::BaseBranch;
+use crate::config::{BranchRemote, CommitId, Config};
+use crate::git;
+use crate::github::{self, RemoteBranch};
+use crate::utils::short;
+
+/// Length of a full hash of a commit
+const FULL_HASH_LEN: usize = 40;
+
+/// How a pinned commit relates to the current head of its branch
+#[derive(Debug, PartialEq, Eq)]
+enum Comparison {
+    /// The pin is the current head
+    UpToDate,
+    /// The branch moved since it was pinned
+    Moved {
+        /// Current head of the branch
+        head: String,
+        /// Commits of the head which the pin does not have
+        ahead: usize,
+        /// Commits of the pin which the head does not have. If there are any,
+        /// the branch was force-pushed
+        behind: usize,
+    },
+    /// The pin could not be found, the branch was probably force-pushed
+    Missing {
+        /// Current head of the branch
+        head: String,
+    },
+    /// The pin is abbreviated, and could not be resolved to a commit which can be fetched
+    Unknown {
+        /// Current head of the branch
+        head: String,
+    },
+}
+
+impl Comparison {
+    /// Compare the `pin` with the current head of the local `branch`, which was fetched from `url`
+    ///
+    /// The GitHub `repo` of the branch, if it has one, resolves abbreviated pins. See [`fetch_pin`]
+    async fn new(
+        pin: &CommitId,
+        branch: &str,
+        url: &str,
+        repo: Option<&str>,
+        use_gh_cli: bool,
+    ) -> anyhow::Result<Self> {
+        let head = git::rev_parse(branch)?;
+
+        // the pin is no longer on the branch if it was force-pushed
+        let pin = if let Ok(pin) = git::rev_parse(pin.as_ref()) {
+            pin
+        } else {
+            match fetch_pin(pin, url, repo, use_gh_cli).await {
+                Ok(pin) => pin,
+                Err(pin) if pin.len() < FULL_HASH_LEN => return Ok(Self::Unknown { head }),
+                Err(_) => return Ok(Self::Missing { head }),
+            }
+        };
+
+        if pin == head {
+            return Ok(Self::UpToDate);
+        }
+
+        Ok(Self::Moved {
+            ahead: git::count_commits(&format!("{pin}..{head}"))?,
+            behind: git::count_commits(&format!("{head}..{pin}"))?,
+            head,
+        })
+    }
+
+    /// Current head of the branch, if it is not the pin
+    fn head(&self) -> Option<&str> {
+        match self {
+            Self::UpToDate => None,
+            Self::Moved { head, .. } | Self::Missing { head } | Self::Unknown { head } => {
+                Some(head)
+            }
+        }
+    }
+
+    /// Short description, e.g. `3 commits ahead, 1 behind, force-pushed`
+    fn describe(&self) -> String {
+        match self {
+            Self::UpToDate => "up to date".to_string(),
+            Self::Moved {
+                ahead, behind: 0, ..
+            } => format!(
+                "{ahead} {} ahead",
+                if *ahead == 1 { "commit" } else { "commits" }
+            ),
+            Self::Moved { ahead, behind, .. } => format!(
+                "{ahead} {} ahead, {behind} behind, force-pushed",
+                if *ahead == 1 { "commit" } else { "commits" }
+            ),
+            Self::Missing { .. } => "pinned commit no longer exists, force-pushed".to_string(),
+            Self::Unknown { .. } => {
+                "pinned commit is unknown, pin the full hash to compare it".to_string()
+            }
+        }
+    }
+}
+
+/// For each pinned pull request, branch and `remote-branch` of the config, compare the pin with
+/// the current head of its branch
+///
+/// If `profile` is given, that profile of the config is used
+#[expect(
+    clippy::print_stdout,
+    reason = "the comparisons are the output of the command"
+)]
+pub async fn outdated(profile: Option<String>, use_gh_cli: bool) -> anyhow::Result<()> {
+    let config = Config::read_with_profile(profile.as_deref())?;
+
+    let use_gh_cli = use_gh_cli || config.use_gh_cli;
+    let mut rows = Vec::new();
+
+    // remote-branch
+
+    if let Some(remote_branch) = &config.remote_branch
+        && let Some(pin) = &remote_branch.commit
+    {
+        let mut unpinned = config.clone();
+        if let Some(remote_branch) = &mut unpinned.remote_branch {
+            remote_branch.commit = None;
+        }
+
+        match BaseBranch::fetch(&unpinned, use_gh_cli).await {
+            Ok(base) => {
+                let comparison = Comparison::new(
+                    pin,
+                    base.branch.as_ref(),
+                    &format!("{}.git", github::repo_url(&config.repo)),
+                    Some(&config.repo),
+                    use_gh_cli,
+                )
+                .await;
+                base.remove()?;
+
+                rows.push((
+                    format!("remote-branch {}", remote_branch.name),
+                    comparison?,
+                    Some(github::repo_url(&config.repo)),
+                    pin,
+                ));
+            }
+            Err(err) => log::error!(
+                "failed to fetch remote-branch {}:\n{err}",
+                remote_branch.name
+            ),
+        }
+    }
+
+    // pull requests
+
+    for pull_request in &config.pull_requests {
+        let Some(pin) = &pull_request.commit else {
+            continue;
+        };
+
+        match github::fetch_pull_request(&config.repo, pull_request.number, None, None, use_gh_cli)
+            .await
+        {
+            Ok((response, remote_branch)) => {
+                let comparison =
+                    compare_fetched(pin, &remote_branch, Some(&config.repo), use_gh_cli).await;
+
+                rows.push((
+                    format!("#{}  {}", pull_request.number, response.title),
+                    comparison?,
+                    Some(github::repo_url(&config.repo)),
+                    pin,
+                ));
+            }
+            Err(err) => log::error!("failed to fetch pull request #{pull_request}:\n{err}"),
+        }
+    }
+
+    // branches
+
+    for branch in &config.branches {
+        let Some(pin) = branch.remote.commit() else {
+            continue;
+        };
+
+        let mut unpinned = branch.remote.clone();
+        unpinned.set_commit(None);
+
+        match github::fetch_branch_remote(&unpinned, use_gh_cli).await {
+            Ok(remote_branch) => {
+                let repo = match &branch.remote {
+                    BranchRemote::GitHub(remote) => {
+                        Some(format!("{}/{}", remote.owner, remote.repo))
+                    }
+                    BranchRemote::Url(_) => None,
+                };
+                let comparison =
+                    compare_fetched(pin, &remote_branch, repo.as_deref(), use_gh_cli).await;
+
+                rows.push((
+                    branch.remote.name(),
+                    comparison?,
+                    repo.as_deref().map(github::repo_url),
+                    pin,
+                ));
+            }
+            Err(err) => log::error!("failed to fetch branch {}:\n{err}", branch.remote),
+        }
+    }
+
+    if rows.is_empty() {
+        log::warn!("There are no pinned pull requests or branches in your config");
+        return Ok(());
+    }
+
+    for (entry, comparison, repo_url, pin) in &rows {
+        let description = match comparison {
+            Comparison::UpToDate => comparison.describe().bright_green(),
+            Comparison::Moved { behind: 0, .. } | Comparison::Unknown { .. } => {
+                comparison.describe().bright_yellow()
+            }
+            Comparison::Moved { .. } | Comparison::Missing { .. } => {
+                comparison.describe().bright_red()
+            }
+        };
+
+        println!("{}  {description}", entry.bright_blue());
+
+        if let Some(head) = comparison.head() {
+            println!(
+                "  {} -> {}{}",
+                short(pin.as_ref()),
+                short(head),
+                repo_url
+                    .as_ref()
+                    .map(|repo_url| format!(
+                        "  {}",
+                        compare_url(repo_url, pin.as_ref(), head).bright_black()
+                    ))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare the `pin` with the head of the `remote_branch` which was just fetched from the GitHub
+/// `repo`, if it is on GitHub, and remove the branch and its remote
+async fn compare_fetched(
+    pin: &CommitId,
+    remote_branch: &RemoteBranch,
+    repo: Option<&str>,
+    use_gh_cli: bool,
+) -> anyhow::Result<Comparison> {
+    let comparison = Comparison::new(
+        pin,
+        remote_branch.branch.local_branch_name.as_ref(),
+        &remote_branch.remote.repository_url,
+        repo,
+        use_gh_cli,
+    )
+    .await;
+
+    git::delete_remote_and_branch(
+        &remote_branch.remote.local_remote_alias,
+        &remote_branch.branch.local_branch_name,
+    )?;
+
+    comparison
+}
+
+/// Fetch the `pin` from `url`, and get its full hash
+///
+/// Servers only fetch commits by their full hash, so if the `pin` is abbreviated it is first
+/// resolved with the API of the GitHub `repo`, if there is one. If the pin could not be fetched,
+/// returns the hash which was tried
+async fn fetch_pin(
+    pin: &CommitId,
+    url: &str,
+    repo: Option<&str>,
+    use_gh_cli: bool,
+) -> Result<String, String> {
+    let mut full_pin = pin.to_string();
+
+    if let Some(repo) = repo
+        && full_pin.len() < FULL_HASH_LEN
+    {
+        match github::fetch_full_commit(repo, pin.as_ref(), use_gh_cli).await {
+            Ok(commit) => full_pin = commit.to_string(),
+            Err(err) => log::debug!("failed to resolve the pin {pin}:\n{err}"),
+        }
+    }
+
+    git::fetch_commit(url, &full_pin)
+        .and_then(|_| git::rev_parse(&full_pin))
+        .map_err(|_| full_pin)
+}
+
+/// URL of GitHub's comparison of the `pin` with the `head`, in the repository at `repo_url`
+fn compare_url(repo_url: &str, pin: &str, head: &str) -> String {
+    format!("{repo_url}/compare/{pin}...{head}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_comparison() {
+        assert_eq!(Comparison::UpToDate.describe(), "up to date");
+        assert_eq!(
+            Comparison::Moved {
+                head: "1a2b3c".to_string(),
+                ahead: 1,
+                behind: 0
+            }
+            .describe(),
+            "1 commit ahead"
+        );
+        assert_eq!(
+            Comparison::Moved {
+                head: "1a2b3c".to_string(),
+                ahead: 3,
+                behind: 2
+            }
+            .describe(),
+            "3 commits ahead, 2 behind, force-pushed"
+        );
+        assert_eq!(
+            Comparison::Unknown {
+                head: "1a2b3c".to_string()
+            }
+            .describe(),
+            "pinned commit is unknown, pin the full hash to compare it"
+        );
+        assert_eq!(
+            compare_url("https://github.com/helix-editor/helix", "a556aee", "1a2b3c"),
+            "https://github.com/helix-editor/helix/compare/a556aee...1a2b3c"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! `patches refresh` subcommand
+
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use colored::Colorize as _;
+use indexmap::IndexSet;
+use itertools::Itertools as _;
+
+use crate::commands::run::{Build, build};
+use crate::config::{self, BranchName, Config, Patch};
+use crate::{git, patch};
+
+/// What happened to a file of a patch when it was applied onto the current base
+#[derive(Debug)]
+enum Refresh {
+    /// The patch applied without changes
+    UpToDate,
+    /// The patch applied, but its diff changed. Contains the regenerated patch
+    Changed(String),
+    /// The current base already contains the changes of the patch
+    Obsolete,
+    /// The patch could not be applied
+    Failed,
+}
+
+/// Apply the `patches` of the config onto its base, pull requests and branches, and rewrite
+/// each patch whose context or line numbers changed
+///
+/// Reports the patches which are obsolete, because the base already contains them.
+///
+/// If `profile` is given, that profile of the config is used
+pub async fn patches_refresh(profile: Option<String>, use_gh_cli: bool) -> anyhow::Result<()> {
+    let mut config = Config::read_with_profile(profile.as_deref())?;
+
+    let use_gh_cli = use_gh_cli || config.use_gh_cli;
+
+    // the patches are applied one by one below, instead of by `build`
+    let patches = mem::take(&mut config.patches);
+
+    if patches.is_empty() {
+        log::warn!("There are no `patches` in your config");
+        return Ok(());
+    }
+
+    let backed_up_files = config::backup::backup()?;
+    let previous_branch = git::get_head_commit()?;
+
+    let Build { branch, .. } = build(&config, use_gh_cli, false).await?;
+
+    let refreshed = refresh_patches(&patches);
+
+    // leave the temporary branch even if refreshing failed, discarding the patch it stopped at
+    if refreshed.is_err() {
+        if git::is_am_in_progress() {
+            git::abort_am()?;
+        }
+        git::reset_hard("HEAD")?;
+    }
+    git::checkout(&previous_branch)?;
+    config::backup::restore(&backed_up_files)?;
+    git::delete_branch(
+        &BranchName::try_new(branch).map_err(|err| anyhow!("invalid branch: {err}"))?,
+    )?;
+
+    let Refreshed {
+        changed,
+        obsolete,
+        failed,
+    } = refreshed?;
+
+    for (file, contents) in &changed {
+        fs::write(file, format!("{contents}\n"))?;
+        log::info!("Refreshed {}", relative_path(file).bright_blue());
+    }
+
+    for file in &obsolete {
+        log::warn!(
+            "{} is obsolete, its changes are already contained in the base",
+            relative_path(file).bright_yellow()
+        );
+    }
+
+    if !failed.is_empty() {
+        log::error!(
+            "Could not apply {}, resolve their conflicts with `patchy run --resolve-patches`",
+            failed
+                .iter()
+                .map(|patch| patch.to_string().bright_red())
+                .join(", ")
+        );
+    }
+
+    if changed.is_empty() && obsolete.is_empty() && failed.is_empty() {
+        log::info!("All patches are up to date");
+    }
+
+    Ok(())
+}
+
+/// Result of applying every patch onto the current branch
+#[derive(Debug, Default)]
+struct Refreshed<'a> {
+    /// Files whose diff changed, with the regenerated patch
+    changed: Vec<(PathBuf, String)>,
+    /// Files whose changes the base already contains
+    obsolete: Vec<PathBuf>,
+    /// Patches which could not be applied
+    failed: Vec<&'a Patch>,
+}
+
+/// Apply each of the `patches` to the current branch in order, and regenerate their files
+fn refresh_patches(patches: &IndexSet<Patch>) -> anyhow::Result<Refreshed<'_>> {
+    let mut refreshed = Refreshed::default();
+
+    for patch in patches {
+        if let Some(download) = &patch.download {
+            log::info!(
+                "Skipping patch {patch}, it is downloaded from {}",
+                download.url.bright_blue()
+            );
+            continue;
+        }
+
+        let files = match patch::files(&patch.name) {
+            Ok(Some(files)) => files,
+            Ok(None) => {
+                log::error!("failed to find patch {patch}, skipping");
+                refreshed.failed.push(patch);
+                continue;
+            }
+            Err(err) => {
+                log::error!("failed to read patch {patch}, skipping\n{err}");
+                refreshed.failed.push(patch);
+                continue;
+            }
+        };
+
+        let before = git::rev_parse("HEAD")?;
+
+        for file in files {
+            match refresh(patch, &file)? {
+                Refresh::UpToDate => {}
+                Refresh::Changed(contents) => refreshed.changed.push((file, contents)),
+                Refresh::Obsolete => refreshed.obsolete.push(file),
+                Refresh::Failed => {
+                    // the rest of a series depends on this patch
+                    git::reset_hard(&before)?;
+                    refreshed.failed.push(patch);
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(refreshed)
+}
+
+/// Apply the `file` of the `patch` to the current branch, and regenerate it
+fn refresh(patch: &Patch, file: &Path) -> anyhow::Result<Refresh> {
+    let format = patch::Format::of(file)?;
+    let before = git::rev_parse("HEAD")?;
+
+    match format.apply(file, patch)? {
+        git::Applied::Cleanly | git::Applied::ThreeWay => {}
+        git::Applied::Empty => return Ok(Refresh::Obsolete),
+        git::Applied::Conflict { hunks, .. } => {
+            format.abort()?;
+            log::error!(
+                "failed to apply patch {patch}:\n{}",
+                hunks.iter().map(|hunk| format!("  {hunk}")).join("\n")
+            );
+            return Ok(Refresh::Failed);
+        }
+    }
+
+    let old = fs::read_to_string(file)?;
+    let new = format.regenerate(&before)?;
+
+    Ok(if diff_of(&old) == diff_of(&new) {
+        Refresh::UpToDate
+    } else {
+        Refresh::Changed(new)
+    })
+}
+
+/// The diff of a patch, without the message and signature that `git format-patch` adds
+fn diff_of(patch: &str) -> &str {
+    let diff = patch
+        .find("\ndiff --git ")
+        .and_then(|start| patch.get(start..))
+        .unwrap_or(patch);
+
+    diff.rfind("\n-- \n")
+        .and_then(|end| diff.get(..end))
+        .unwrap_or(diff)
+        .trim_end()
+}
+
+/// Path of the `file`, relative to the root of the repository
+fn relative_path(file: &Path) -> String {
+    file.strip_prefix(&*git::ROOT)
+        .map_or_else(|_| file.to_path_buf(), PathBuf::from)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_of_patch() {
+        let diff = "
+diff --git a/f b/f
+index 1..2 100644
+--- a/f
++++ b/f
+@@ -1 +1 @@
+-a
++b";
+        let patch = format!(
+            "From 1a2b3c Mon Sep 17 00:00:00 2001
+From: Someone <someone@example.com>
+Subject: [PATCH] Fix
+
+---
+ f | 2 +-
+{diff}\n-- \n2.39.5\n"
+        );
+
+        assert_eq!(diff_of(&patch), diff);
+        assert_eq!(diff_of("not a patch"), "not a patch");
+    }
+}
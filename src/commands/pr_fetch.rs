@@ -1,91 +1,404 @@
 //! `pr-fetch` subcommand
 
-use anyhow::{Context as _, anyhow};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
 use colored::Colorize as _;
+use futures::future::join_all;
+
+use crate::commands::run::merge;
+use crate::config::{BranchName, CommitId, MergeStrategy, PrNumber, Repository};
+use crate::git;
+use crate::github::{self, PrData, RemoteBranch};
+use crate::remote_url::RemoteUrl;
+
+/// A pull request to fetch, e.g. `12254` or `https://github.com/helix-editor/helix/pull/12254`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequestRef {
+    /// Repository of the pull request, e.g. `helix-editor/helix`, if it was given as a URL
+    pub repo: Option<String>,
+    /// Number of the pull request
+    pub number: PrNumber,
+}
+
+impl FromStr for PullRequestRef {
+    type Err = anyhow::Error;
+
+    /// Parse either the number of a pull request, or its URL
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(number) = s.trim_start_matches('#').parse::<PrNumber>() {
+            return Ok(Self { repo: None, number });
+        }
+
+        let host = github::host();
+        let invalid = || {
+            anyhow!(
+                "invalid pull request: {s}. Expected a number, or a URL like https://{host}/owner/repo/pull/123"
+            )
+        };
+
+        let path = s
+            .strip_prefix("https://")
+            .or_else(|| s.strip_prefix("http://"))
+            .unwrap_or(s)
+            .strip_prefix(host.as_str())
+            .and_then(|path| path.strip_prefix('/'))
+            .ok_or_else(invalid)?;
 
-use crate::config::{BranchName, CommitId, PrNumber, Remote, RepoName, RepoOwner};
-use crate::{git, github};
+        // remove e.g. `?diff=split` or `#issuecomment-123`
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+
+        let mut parts = path.split('/');
+        let (Some(owner), Some(repo), Some("pull"), Some(number)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+
+        if owner.is_empty() || repo.is_empty() {
+            return Err(invalid());
+        }
 
-/// Fetch the given `pr` of `remote` at `commit` and store it in local `branch`
+        Ok(Self {
+            repo: Some(format!("{owner}/{repo}")),
+            number: number.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A positional argument of `pr-fetch`: a pull request, or the repository to fetch them from
 ///
-/// If `checkout`, `--checkout` the `branch`
+/// This keeps `patchy pr-fetch 12254 helix-editor/helix` working
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrFetchArg {
+    /// e.g. `12254`
+    PullRequest(PullRequestRef),
+    /// e.g. `helix-editor/helix`
+    Repository(Repository),
+}
+
+impl FromStr for PrFetchArg {
+    type Err = anyhow::Error;
+
+    /// Parse a pull request, or else a repository
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<PullRequestRef>()
+            .map(Self::PullRequest)
+            .or_else(|err| {
+                s.parse::<Repository>()
+                    .map(Self::Repository)
+                    .map_err(|_| anyhow!("{err}, or the repository to fetch from like owner/repo"))
+            })
+    }
+}
+
+/// Split the positional `args` of `pr-fetch` into the pull requests,
+/// and the repository to fetch them from. It can also be given as `repo`
+pub fn split_args(
+    args: Vec<PrFetchArg>,
+    repo: Option<Repository>,
+) -> anyhow::Result<(Vec<PullRequestRef>, Option<Repository>)> {
+    let mut pull_requests = Vec::new();
+    let mut repos = Vec::from_iter(repo);
+
+    for arg in args {
+        match arg {
+            PrFetchArg::PullRequest(pull_request) => pull_requests.push(pull_request),
+            PrFetchArg::Repository(repo) => repos.push(repo),
+        }
+    }
+
+    if pull_requests.is_empty() {
+        bail!("no pull requests to fetch");
+    }
+
+    let repo = repos.pop();
+    if !repos.is_empty() {
+        bail!("pull requests can only be fetched from one repository");
+    }
+
+    Ok((pull_requests, repo))
+}
+
+/// Fetch the given `pull_requests` of `remote` and store each of them in a local branch
+///
+/// If `remote` is omitted, uses the repository that the git remote `remote_name` points to.
+/// Pull requests given as URLs use the repository of the URL.
+///
+/// `branch` and `commit` can only be used when fetching a single pull request.
+///
+/// If `combine` is given, a branch of that name is created from the current branch,
+/// which merges all of the fetched pull requests.
+///
+/// If `checkout`, checks out the combined branch, or the first fetched pull request
+#[expect(
+    clippy::too_many_arguments,
+    reason = "these are the command-line arguments of `pr-fetch`"
+)]
 pub async fn pr_fetch(
-    pr: PrNumber,
-    remote: Option<Remote>,
+    pull_requests: Vec<PullRequestRef>,
+    remote: Option<Repository>,
+    remote_name: &str,
     branch: Option<BranchName>,
     commit: Option<CommitId>,
     checkout: bool,
+    combine: Option<BranchName>,
     use_gh_cli: bool,
 ) -> anyhow::Result<()> {
-    pub const GITHUB_REMOTE_PREFIX: &str = "git@github.com:";
-    pub const GITHUB_REMOTE_SUFFIX: &str = ".git";
-
-    // The user hasn't provided a custom remote, so we're going to try `origin`
-    let remote = remote.map_or_else(
-        || -> anyhow::Result<Remote> {
-            let remote = git::get_remote_url("origin")?;
-            let err = || anyhow!("git command returned invalid remote: {remote}");
-
-            if remote.starts_with(GITHUB_REMOTE_PREFIX) && remote.ends_with(GITHUB_REMOTE_SUFFIX) {
-                let start = GITHUB_REMOTE_PREFIX.len();
-                let end = remote.len() - GITHUB_REMOTE_SUFFIX.len();
-                let (owner, repo) = remote
-                    .get(start..end)
-                    .and_then(|x| x.split_once('/'))
-                    .with_context(err)?;
-                Ok(Remote {
-                    owner: RepoOwner::try_new(owner)?,
-                    repo: RepoName::try_new(repo)?,
-                    branch: BranchName::try_new("main").expect("`main` is a valid branch name"),
-                    commit: None,
-                })
-            } else {
-                Err(err())
-            }
-        },
-        Ok,
-    )?;
-
-    let Ok((response, info)) = github::fetch_pull_request(
-        &format!("{}/{}", remote.owner, remote.repo),
-        pr,
-        branch,
-        commit.as_ref(),
-        use_gh_cli,
+    if pull_requests.len() > 1 && (branch.is_some() || commit.is_some()) {
+        bail!("`--branch` and `--commit` can only be used when fetching a single pull request");
+    }
+
+    let default_repo = if pull_requests.iter().any(|pr| pr.repo.is_none()) {
+        Some(default_repo(remote, remote_name)?)
+    } else {
+        None
+    };
+
+    let repos = pull_requests
+        .iter()
+        .map(|pr| {
+            pr.repo
+                .clone()
+                .or_else(|| default_repo.clone())
+                .expect("default repository exists if a pull request has no repository")
+        })
+        .collect::<Vec<_>>();
+
+    // the API can be called concurrently, but git can't fetch concurrently
+    // into the same repository, so the branches are fetched one by one
+    let responses = join_all(
+        pull_requests
+            .iter()
+            .zip(&repos)
+            .map(|(pr, repo)| github::fetch_pr_data(repo, pr.number, use_gh_cli)),
     )
-    .await
-    .inspect_err(|err| {
-        log::error!("{err}");
-    }) else {
-        return Ok(());
+    .await;
+
+    let mut fetched: Vec<(PrNumber, PrData, RemoteBranch)> = Vec::new();
+
+    for (pr, response) in pull_requests.iter().zip(responses) {
+        let Ok(response) = response.inspect_err(|err| log::error!("{err}")) else {
+            continue;
+        };
+
+        let Ok(info) = github::fetch_pull_request_branch(
+            &response,
+            pr.number,
+            branch.clone(),
+            commit.as_ref(),
+        )
+        .inspect_err(|err| log::error!("{err}")) else {
+            continue;
+        };
+
+        log::info!(
+            "Fetched pull request {} available at branch {}{}",
+            crate::utils::format_pr(pr.number, &response.title, &response.html_url),
+            info.branch.local_branch_name.as_ref().bright_cyan(),
+            commit
+                .clone()
+                .map(|commit_hash| {
+                    format!(", at commit {}", commit_hash.as_ref().bright_yellow())
+                })
+                .unwrap_or_default()
+        );
+
+        // Attempt to cleanup after ourselves
+        let _ = git::remove_remote(&info.remote.local_remote_alias);
+
+        fetched.push((pr.number, response, info));
+    }
+
+    if fetched.len() > 1 {
+        print_table(&fetched);
+    }
+
+    let to_checkout = if let Some(combined) = combine {
+        combine_branches(&combined, &fetched)?;
+        Some(combined)
+    } else {
+        fetched
+            .first()
+            .map(|(_, _, info)| info.branch.local_branch_name.clone())
     };
 
-    log::info!(
-        "Fetched pull request {} available at branch {}{}",
-        crate::utils::format_pr(pr, &response.title, &response.html_url),
-        info.branch.local_branch_name.as_ref().bright_cyan(),
-        commit
-            .clone()
-            .map(|commit_hash| { format!(", at commit {}", commit_hash.as_ref().bright_yellow()) })
-            .unwrap_or_default()
-    );
-
-    // Attempt to cleanup after ourselves
-    let _ = git::remove_remote(&info.remote.local_remote_alias);
-
-    if checkout {
-        if let Err(checkout_err) = git::checkout(info.branch.local_branch_name.as_ref()) {
-            log::error!(
-                "Could not check out branch {}:\n{checkout_err}",
-                info.branch.local_branch_name
-            );
+    if checkout && let Some(branch) = to_checkout {
+        if let Err(checkout_err) = git::checkout(branch.as_ref()) {
+            log::error!("Could not check out branch {branch}:\n{checkout_err}");
+        } else {
+            log::info!("Automatically checked out branch: {branch}");
+        }
+    }
+
+    Ok(())
+}
+
+/// The repository of `remote`, or of the git remote `remote_name` if it is omitted
+pub fn default_repo(remote: Option<Repository>, remote_name: &str) -> anyhow::Result<String> {
+    if let Some(remote) = remote {
+        return Ok(remote.to_string());
+    }
+
+    let url = git::get_remote_url(remote_name)
+        .map_err(|err| anyhow!("failed to get the URL of remote `{remote_name}`:\n{err}"))?;
+    let url = RemoteUrl::parse(&url)
+        .map_err(|err| anyhow!("failed to parse the URL of remote `{remote_name}`: {err}"))?;
+
+    if !url.is_github() {
+        bail!(
+            "remote `{remote_name}` is on {}, but pull requests are fetched from {}. \
+             If it is GitHub Enterprise, set `github-host = \"{}\"` in the config",
+            url.host,
+            github::host(),
+            url.host
+        );
+    }
+
+    Ok(format!("{}/{}", url.owner, url.repo))
+}
+
+/// Print the local branch of each fetched pull request
+#[expect(
+    clippy::print_stdout,
+    reason = "the table is the output of the command"
+)]
+fn print_table(fetched: &[(PrNumber, PrData, RemoteBranch)]) {
+    let rows = fetched
+        .iter()
+        .map(|(number, response, info)| {
+            (
+                format!("#{number}"),
+                info.branch.local_branch_name.to_string(),
+                &response.title,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let number_width = rows.iter().map(|(number, _, _)| number.len()).max();
+    let branch_width = rows.iter().map(|(_, branch, _)| branch.len()).max();
+
+    println!();
+    for (number, branch, title) in rows {
+        println!(
+            "{}  {}  {}",
+            format!("{number:<0$}", number_width.unwrap_or_default()).bright_blue(),
+            format!("{branch:<0$}", branch_width.unwrap_or_default()).bright_cyan(),
+            title.italic()
+        );
+    }
+    println!();
+}
+
+/// Create the branch `combined` from the current branch, and merge all `fetched` pull requests into it
+///
+/// Returns to the current branch afterwards
+fn combine_branches(
+    combined: &BranchName,
+    fetched: &[(PrNumber, PrData, RemoteBranch)],
+) -> anyhow::Result<()> {
+    let previous_branch = git::get_head_commit()?;
+
+    git::create_branch(combined.as_ref())
+        .map_err(|err| anyhow!("failed to create branch {combined}:\n{err}"))?;
+
+    for (number, response, info) in fetched {
+        if let Err(err) = merge(
+            &info.branch.local_branch_name,
+            &info.branch.upstream_branch_name,
+            MergeStrategy::Merge,
+            &format!("patchy: Merge pull request #{number} {}", response.title).into(),
+        ) {
+            log::error!("failed to merge #{number} into {combined}: {err}");
         } else {
             log::info!(
-                "Automatically checked out the first branch: {}",
-                info.branch.local_branch_name
+                "Merged {} into {}",
+                crate::utils::format_pr(*number, &response.title, &response.html_url),
+                combined.as_ref().bright_cyan()
             );
         }
     }
 
+    git::checkout(&previous_branch)?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pull_request_ref() {
+        let cases = [
+            ("12254", None),
+            ("#12254", None),
+            (
+                "https://github.com/helix-editor/helix/pull/12254",
+                Some("helix-editor/helix"),
+            ),
+            (
+                "https://github.com/helix-editor/helix/pull/12254/files",
+                Some("helix-editor/helix"),
+            ),
+            (
+                "http://github.com/helix-editor/helix/pull/12254#issuecomment-1",
+                Some("helix-editor/helix"),
+            ),
+            (
+                "github.com/helix-editor/helix/pull/12254?diff=split",
+                Some("helix-editor/helix"),
+            ),
+        ];
+
+        for (input, repo) in cases {
+            assert_eq!(
+                input.parse::<PullRequestRef>().unwrap(),
+                PullRequestRef {
+                    repo: repo.map(str::to_owned),
+                    number: 12254.try_into().unwrap(),
+                },
+                "input: {input:?}"
+            );
+        }
+
+        for input in [
+            "0",
+            "helix",
+            "helix-editor/helix",
+            "https://github.com/helix-editor/helix/issues/12254",
+            "https://gitlab.com/helix-editor/helix/pull/12254",
+            "https://github.com/helix-editor/helix/pull/abc",
+        ] {
+            input
+                .parse::<PullRequestRef>()
+                .expect_err(&format!("input: {input:?}"));
+        }
+    }
+
+    #[test]
+    fn positional_repository() {
+        let args = |args: &[&str]| {
+            args.iter()
+                .map(|arg| arg.parse::<PrFetchArg>())
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let helix = "helix-editor/helix".parse::<Repository>().unwrap();
+
+        let (pull_requests, repo) =
+            split_args(args(&["12254", "helix-editor/helix"]).unwrap(), None).unwrap();
+        assert_eq!(
+            pull_requests,
+            [PullRequestRef {
+                repo: None,
+                number: 12254.try_into().unwrap()
+            }]
+        );
+        assert_eq!(repo, Some(helix.clone()));
+
+        split_args(args(&["12254", "helix-editor/helix"]).unwrap(), Some(helix)).unwrap_err();
+        split_args(args(&["helix-editor/helix"]).unwrap(), None).unwrap_err();
+        args(&["12254", "helix-editor/helix/master"]).unwrap_err();
+        args(&["12254", "helix-editor/helix @ 1a2b3c"]).unwrap_err();
+    }
+}
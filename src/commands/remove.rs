@@ -0,0 +1,43 @@
+//! `remove` subcommand
+
+use colored::Colorize as _;
+
+use crate::cli::RemoveEntry;
+use crate::config::{Config, edit::ConfigFile};
+use crate::github;
+
+/// Remove the `entry` from the config file
+pub async fn remove(entry: RemoveEntry, use_gh_cli: bool) -> anyhow::Result<()> {
+    let mut file = ConfigFile::open()?;
+
+    let removed = match entry {
+        RemoveEntry::Pr { pr } => {
+            file.remove_pull_request(pr)?;
+            format!("pull request #{pr}")
+        }
+        RemoveEntry::Branch { remote } => {
+            if file.remove_branch(&remote, None).is_err() {
+                // e.g. `owner/repo` is in the config, and `owner/repo/master` is removed
+                let use_gh_cli = use_gh_cli || Config::read().is_ok_and(|config| config.use_gh_cli);
+                let default_branch =
+                    github::fetch_branch_remote_default_branch(&remote, use_gh_cli)
+                        .await
+                        .inspect_err(|err| log::debug!("failed to find the default branch: {err}"))
+                        .ok();
+
+                file.remove_branch(&remote, default_branch.as_ref())?;
+            }
+            format!("branch {remote}")
+        }
+        RemoveEntry::Patch { name } => {
+            file.remove_patch(&name)?;
+            format!("patch {name}")
+        }
+    };
+
+    file.save()?;
+
+    log::info!("Removed {}", removed.bright_blue());
+
+    Ok(())
+}
@@ -0,0 +1,397 @@
+//! `report` subcommand
+
+use anyhow::bail;
+use itertools::Itertools as _;
+
+use crate::cli::ReportFormat;
+use crate::config::Config;
+use crate::github;
+use crate::lock::{self, Lockfile, Outcome};
+use crate::utils::short;
+
+/// A piece of text in the report
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inline {
+    /// Plain text
+    Text(String),
+    /// Inline code, e.g. a commit hash
+    Code(String),
+    /// A link with plain text
+    Link(String, String),
+    /// A link with inline code
+    CodeLink(String, String),
+}
+
+/// A section of the report, with a list of items
+#[derive(Debug)]
+struct Section {
+    /// Heading of the section
+    title: &'static str,
+    /// Each item of the list
+    items: Vec<Vec<Inline>>,
+}
+
+/// The contents of the report, independent of its format
+#[derive(Debug)]
+struct Report {
+    /// Heading of the report
+    title: Vec<Inline>,
+    /// Paragraph below the heading
+    summary: Vec<Inline>,
+    /// Sections which are not empty
+    sections: Vec<Section>,
+}
+
+/// Print a description of what `local-branch` contains, from the lockfile which the last
+/// `patchy run` committed to it
+///
+/// If `profile` is given, the `local-branch` of that profile is described
+#[expect(
+    clippy::print_stdout,
+    reason = "the report is the output of the command"
+)]
+pub fn report(format: ReportFormat, profile: Option<&str>) -> anyhow::Result<()> {
+    let config = Config::read_with_profile(profile)?;
+
+    let local_branch = config.local_branch.as_ref();
+
+    let Some(lockfile) = Lockfile::read_from(local_branch)? else {
+        bail!(
+            "{local_branch} has no {}, create it with `patchy run`",
+            lock::FILE
+        );
+    };
+
+    let report = Report::new(local_branch, &lockfile);
+
+    print!(
+        "{}",
+        match format {
+            ReportFormat::Markdown => report.to_markdown(),
+            ReportFormat::Html => report.to_html(),
+        }
+    );
+
+    Ok(())
+}
+
+impl Report {
+    /// Describe the `lockfile` of the `local_branch`
+    fn new(local_branch: &str, lockfile: &Lockfile) -> Self {
+        let base = &lockfile.base;
+        let repo_url = github::repo_url(&base.repo);
+
+        let mut summary = vec![
+            Inline::Text("Based on ".to_string()),
+            Inline::CodeLink(base.repo.clone(), repo_url.clone()),
+            Inline::Text(" ".to_string()),
+            Inline::Code(base.branch.clone()),
+        ];
+        if let Some(commit) = &base.commit {
+            summary.push(Inline::Text(" at ".to_string()));
+            summary.push(Inline::CodeLink(
+                short(commit.as_ref()).to_string(),
+                format!("{repo_url}/commit/{commit}"),
+            ));
+        }
+        summary.push(Inline::Text(".".to_string()));
+
+        let mut pull_requests = Vec::new();
+        let mut branches = Vec::new();
+        let mut patches = Vec::new();
+        let mut failed = Vec::new();
+
+        for pull_request in &lockfile.pull_requests {
+            let mut item = vec![match &pull_request.url {
+                Some(url) => Inline::Link(format!("#{}", pull_request.number), url.clone()),
+                None => Inline::Text(format!("#{}", pull_request.number)),
+            }];
+            if let Some(title) = &pull_request.title {
+                item.push(Inline::Text(format!(" {title}")));
+            }
+
+            match pull_request.outcome {
+                Outcome::Merged => {
+                    if let Some(author) = &pull_request.author {
+                        item.push(Inline::Text(" by ".to_string()));
+                        item.push(Inline::Link(format!("@{author}"), github::repo_url(author)));
+                    }
+                    if let Some(head) = &pull_request.head {
+                        item.push(Inline::Text(" at ".to_string()));
+                        item.push(match &pull_request.url {
+                            Some(url) => Inline::CodeLink(
+                                short(head.as_ref()).to_string(),
+                                format!("{url}/commits/{head}"),
+                            ),
+                            None => Inline::Code(short(head.as_ref()).to_string()),
+                        });
+                    }
+                    pull_requests.push(item);
+                }
+                Outcome::Failed | Outcome::Skipped | Outcome::Dropped => {
+                    item.push(Inline::Text(format!(
+                        ": {}{}",
+                        pull_request.outcome.as_str(),
+                        pull_request
+                            .reason
+                            .as_ref()
+                            .map(|reason| format!(", {reason}"))
+                            .unwrap_or_default()
+                    )));
+                    failed.push(item);
+                }
+                Outcome::Disabled => {}
+            }
+        }
+
+        let entries = lockfile
+            .branches
+            .iter()
+            .map(|branch| (&branch.remote, &branch.subject, branch.outcome, false))
+            .chain(
+                lockfile
+                    .patches
+                    .iter()
+                    .map(|patch| (&patch.name, &patch.subject, patch.outcome, true)),
+            );
+
+        for (name, subject, outcome, is_patch) in entries {
+            let mut item = vec![Inline::Code(name.clone())];
+
+            match outcome {
+                Outcome::Merged => {
+                    if let Some(subject) = subject {
+                        item.push(Inline::Text(format!(": {subject}")));
+                    }
+                    if is_patch {
+                        patches.push(item);
+                    } else {
+                        branches.push(item);
+                    }
+                }
+                Outcome::Failed | Outcome::Skipped | Outcome::Dropped => {
+                    item.push(Inline::Text(format!(": {}", outcome.as_str())));
+                    failed.push(item);
+                }
+                Outcome::Disabled => {}
+            }
+        }
+
+        Self {
+            title: vec![
+                Inline::Text("What's in ".to_string()),
+                Inline::Code(local_branch.to_string()),
+            ],
+            summary,
+            sections: [
+                ("Pull requests", pull_requests),
+                ("Branches", branches),
+                ("Patches", patches),
+                ("Not merged in the last run", failed),
+            ]
+            .into_iter()
+            .filter(|(_, items)| !items.is_empty())
+            .map(|(title, items)| Section { title, items })
+            .collect(),
+        }
+    }
+
+    /// The report as Markdown, e.g. for a README or the body of a GitHub release
+    fn to_markdown(&self) -> String {
+        /// Markdown of the `inlines`
+        fn render(inlines: &[Inline]) -> String {
+            inlines
+                .iter()
+                .map(|inline| match inline {
+                    Inline::Text(text) => escape_markdown(text),
+                    Inline::Code(code) => format!("`{}`", code.replace('`', "")),
+                    Inline::Link(text, url) => format!("[{}]({url})", escape_markdown(text)),
+                    Inline::CodeLink(code, url) => format!("[`{}`]({url})", code.replace('`', "")),
+                })
+                .collect()
+        }
+
+        let sections = self
+            .sections
+            .iter()
+            .map(|section| {
+                format!(
+                    "\n### {}\n\n{}\n",
+                    section.title,
+                    section
+                        .items
+                        .iter()
+                        .map(|item| format!("- {}", render(item)))
+                        .join("\n")
+                )
+            })
+            .join("");
+
+        format!(
+            "## {}\n\n{}\n{sections}",
+            render(&self.title),
+            render(&self.summary)
+        )
+    }
+
+    /// The report as HTML, without a surrounding document
+    fn to_html(&self) -> String {
+        /// HTML of the `inlines`
+        fn render(inlines: &[Inline]) -> String {
+            inlines
+                .iter()
+                .map(|inline| match inline {
+                    Inline::Text(text) => escape_html(text),
+                    Inline::Code(code) => format!("<code>{}</code>", escape_html(code)),
+                    Inline::Link(text, url) => {
+                        format!("<a href=\"{}\">{}</a>", escape_html(url), escape_html(text))
+                    }
+                    Inline::CodeLink(code, url) => format!(
+                        "<a href=\"{}\"><code>{}</code></a>",
+                        escape_html(url),
+                        escape_html(code)
+                    ),
+                })
+                .collect()
+        }
+
+        let sections = self
+            .sections
+            .iter()
+            .map(|section| {
+                format!(
+                    "<h3>{}</h3>\n<ul>\n{}\n</ul>\n",
+                    section.title,
+                    section
+                        .items
+                        .iter()
+                        .map(|item| format!("  <li>{}</li>", render(item)))
+                        .join("\n")
+                )
+            })
+            .join("");
+
+        format!(
+            "<h2>{}</h2>\n<p>{}</p>\n{sections}",
+            render(&self.title),
+            render(&self.summary)
+        )
+    }
+}
+
+/// Escape the characters which have a meaning in Markdown
+fn escape_markdown(text: &str) -> String {
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut escaped, ch| {
+            if matches!(ch, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>') {
+                escaped.push('\\');
+            }
+            escaped.push(ch);
+            escaped
+        })
+}
+
+/// Escape the characters which have a meaning in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lock::{Base, Branch, Patch, PullRequest};
+
+    use super::*;
+
+    #[test]
+    fn render_report() {
+        let lockfile = Lockfile {
+            base: Base {
+                repo: "helix-editor/helix".to_string(),
+                branch: "master".to_string(),
+                commit: Some("a1b2c3d4e5".try_into().unwrap()),
+            },
+            queries: vec![],
+            pull_requests: vec![
+                PullRequest {
+                    number: 12254.try_into().unwrap(),
+                    title: Some("Add <file> explorer".to_string()),
+                    url: Some("https://github.com/helix-editor/helix/pull/12254".to_string()),
+                    author: Some("nik-rev".to_string()),
+                    head: Some("deadbeef12".try_into().unwrap()),
+                    query: None,
+                    outcome: Outcome::Merged,
+                    reason: None,
+                    hooks: None,
+                },
+                PullRequest {
+                    number: 8908.try_into().unwrap(),
+                    title: None,
+                    url: None,
+                    author: None,
+                    head: None,
+                    query: None,
+                    outcome: Outcome::Failed,
+                    reason: Some("merge conflict".to_string()),
+                    hooks: None,
+                },
+            ],
+            branches: vec![Branch {
+                remote: "owner/repo/feat".to_string(),
+                commit: None,
+                subject: Some("Add feature".to_string()),
+                outcome: Outcome::Merged,
+                hooks: None,
+            }],
+            patches: vec![Patch {
+                name: "remove-tab".to_string(),
+                subject: None,
+                outcome: Outcome::Disabled,
+                hooks: None,
+            }],
+        };
+
+        let report = Report::new("patchy", &lockfile);
+
+        pretty_assertions::assert_eq!(
+            report.to_markdown(),
+            "## What's in `patchy`
+
+Based on [`helix-editor/helix`](https://github.com/helix-editor/helix) `master` at [`a1b2c3d`](https://github.com/helix-editor/helix/commit/a1b2c3d4e5).
+
+### Pull requests
+
+- [#12254](https://github.com/helix-editor/helix/pull/12254) Add \\<file\\> explorer by [@nik-rev](https://github.com/nik-rev) at [`deadbee`](https://github.com/helix-editor/helix/pull/12254/commits/deadbeef12)
+
+### Branches
+
+- `owner/repo/feat`: Add feature
+
+### Not merged in the last run
+
+- #8908: failed, merge conflict
+"
+        );
+
+        pretty_assertions::assert_eq!(
+            report.to_html(),
+            r#"<h2>What's in <code>patchy</code></h2>
+<p>Based on <a href="https://github.com/helix-editor/helix"><code>helix-editor/helix</code></a> <code>master</code> at <a href="https://github.com/helix-editor/helix/commit/a1b2c3d4e5"><code>a1b2c3d</code></a>.</p>
+<h3>Pull requests</h3>
+<ul>
+  <li><a href="https://github.com/helix-editor/helix/pull/12254">#12254</a> Add &lt;file&gt; explorer by <a href="https://github.com/nik-rev">@nik-rev</a> at <a href="https://github.com/helix-editor/helix/pull/12254/commits/deadbeef12"><code>deadbee</code></a></li>
+</ul>
+<h3>Branches</h3>
+<ul>
+  <li><code>owner/repo/feat</code>: Add feature</li>
+</ul>
+<h3>Not merged in the last run</h3>
+<ul>
+  <li>#8908: failed, merge conflict</li>
+</ul>
+"#
+        );
+    }
+}
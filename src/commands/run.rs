@@ -1,24 +1,42 @@
 //! `run` subcommand
 
 use crate::cli::Confirm;
-use crate::config::{self, BranchName, Config, PrNumber, PullRequest};
+use crate::config::{
+    self, BranchEntry, BranchName, CommitId, Config, EntryOptions, MergeStrategy, Message, Patch,
+    PrNumber, PullRequest,
+};
+use crate::lock::{self, HookResult, Lockfile, Outcome};
+use crate::remote_url::RemoteUrl;
 use anyhow::Result;
+use itertools::Itertools as _;
 use std::fs;
+use std::path::Path;
 
 use anyhow::{anyhow, bail};
 use colored::Colorize as _;
 
 use crate::github::{self, Branch, Remote, RemoteBranch};
-use crate::utils::{format_pr, format_url, with_uuid};
-use crate::{commands, confirm_prompt, git};
+use crate::utils::{format_note, format_pr, format_url, with_uuid};
+use crate::{commands, confirm_prompt, git, hooks, patch, select};
 
 /// Run patchy, if `yes` then there will be no prompt
-pub async fn run(confirm: Option<Confirm>, use_gh_cli: bool) -> Result<()> {
-    let root = config::ROOT.as_str();
-
-    let Ok(config_string) = fs::read_to_string(&*config::FILE_PATH) else {
+///
+/// If `profile` is given, that profile of the config is used
+///
+/// If `resolve_patches`, patches with conflicts are left for the user to resolve
+///
+/// If `interactive`, the user chooses which entries of the config to use
+pub async fn run(
+    confirm: Option<Confirm>,
+    profile: Option<String>,
+    resolve_patches: bool,
+    interactive: bool,
+    use_gh_cli: bool,
+) -> Result<()> {
+    if !config::FILE_PATH.exists() {
         log::error!(
-            "Could not find configuration file at {root}/{}",
+            "Could not find configuration file at {}/{}",
+            config::ROOT.as_str(),
             config::FILE
         );
 
@@ -38,22 +56,157 @@ pub async fn run(confirm: Option<Confirm>, use_gh_cli: bool) -> Result<()> {
         // it's empty there's no reason why the user would want to run it.
 
         return Ok(());
+    }
+
+    let mut config = Config::read_with_profile(profile.as_deref())?;
+
+    let use_gh_cli = use_gh_cli || config.use_gh_cli;
+
+    if interactive {
+        select::select(&mut config, profile.as_deref(), use_gh_cli).await?;
+    }
+
+    let Build {
+        branch: temporary_branch,
+        lockfile,
+        previous_branch,
+        regenerated,
+    } = build(&config, use_gh_cli, resolve_patches).await?;
+
+    report_hooks(&lockfile);
+
+    let failed = lockfile.failed();
+    if !failed.is_empty() {
+        log::warn!(
+            "Could not merge {}",
+            failed.iter().map(|entry| entry.bright_yellow()).join(", ")
+        );
+    }
+
+    let overwrite_branch = match confirm {
+        Some(Confirm::Yes) => true,
+        Some(Confirm::No) => false,
+        None => confirm_prompt!(
+            "Overwrite branch {}? This is irreversible.",
+            config.local_branch.as_ref().cyan()
+        ),
     };
 
-    log::debug!("Using configuration file {}", config::FILE_PATH.display());
+    if overwrite_branch {
+        git::rename_branch(&temporary_branch, config.local_branch.as_ref())?;
+        log::info!("Success!");
 
-    let config = toml::from_str::<Config>(&config_string).map_err(|err| {
-        anyhow!(
-            "Could not parse `{root}/{}` configuration file:\n{err}",
-            config::FILE
-        )
-    })?;
+        if let Some(push_remote) = &config.push_remote {
+            git::force_push(push_remote, config.local_branch.as_ref()).map_err(|err| {
+                anyhow!(
+                    "failed to push {} to {push_remote}:\n{err}",
+                    config.local_branch
+                )
+            })?;
+            log::info!(
+                "Pushed {} to {}",
+                config.local_branch.as_ref().cyan(),
+                push_remote.bright_cyan()
+            );
+        }
+    } else {
+        let overwrite_command = format!(
+            "git branch --move --force {temporary_branch} {}",
+            config.local_branch
+        );
+        log::info!(
+            "You can still manually overwrite {} with:\n  {overwrite_command}\n",
+            config.local_branch.as_ref().cyan(),
+        );
+    }
 
-    let config::Branch {
-        name: remote_branch,
-        commit,
-    } = config.remote_branch;
+    // `local-branch` already has them, if it was checked out before
+    if overwrite_branch && previous_branch == config.local_branch.as_ref() {
+        return Ok(());
+    }
+
+    write_back_regenerated(&previous_branch, &regenerated)
+}
 
+/// Write the `regenerated` patches to the config directory of `previous_branch`,
+/// which the config was read from. Otherwise, they would only be on patchy's branch
+fn write_back_regenerated(
+    previous_branch: &str,
+    regenerated: &[config::backup::FileBackup],
+) -> Result<()> {
+    if regenerated.is_empty() {
+        return Ok(());
+    }
+
+    git::checkout(previous_branch)
+        .map_err(|err| anyhow!("failed to check out {previous_branch}:\n{err}"))?;
+    config::backup::restore(regenerated)?;
+
+    log::info!(
+        "Wrote the regenerated patches to {}, commit them to keep them",
+        previous_branch.bright_cyan()
+    );
+
+    Ok(())
+}
+
+/// Report the result of the `after-entry` hooks for each entry of the `lockfile`
+fn report_hooks(lockfile: &Lockfile) {
+    let pull_requests = lockfile.pull_requests.iter().filter_map(|pull_request| {
+        pull_request.hooks.map(|hooks| {
+            (
+                format!("#{}", pull_request.number),
+                pull_request.outcome,
+                hooks,
+            )
+        })
+    });
+    let branches = lockfile.branches.iter().filter_map(|branch| {
+        branch
+            .hooks
+            .map(|hooks| (branch.remote.clone(), branch.outcome, hooks))
+    });
+    let patches = lockfile.patches.iter().filter_map(|patch| {
+        patch
+            .hooks
+            .map(|hooks| (patch.name.clone(), patch.outcome, hooks))
+    });
+
+    let results = pull_requests
+        .chain(branches)
+        .chain(patches)
+        .map(|(name, outcome, hooks)| {
+            let result = match (hooks, outcome) {
+                (HookResult::Passed, _) => "passed".bright_green(),
+                (HookResult::Failed, Outcome::Dropped) => "failed, dropped".bright_red(),
+                (HookResult::Failed, _) => "failed, kept".bright_yellow(),
+            };
+            format!("  {name}: {result}")
+        })
+        .collect::<Vec<_>>();
+
+    if !results.is_empty() {
+        log::info!("Hooks after each entry:\n{}", results.join("\n"));
+    }
+}
+
+/// Result of [`build`]
+#[derive(Debug)]
+pub struct Build {
+    /// Temporary branch which contains everything from the config, it is checked out
+    pub branch: String,
+    /// What happened to each entry of the config
+    pub lockfile: Lockfile,
+    /// Branch which was checked out before the build
+    pub previous_branch: String,
+    /// Patches which were regenerated from the resolution of their conflicts
+    pub regenerated: Vec<config::backup::FileBackup>,
+}
+
+/// Merge everything from the `config` into a new temporary branch, and check it out
+///
+/// If `resolve_patches`, patches with conflicts are left for the user to resolve
+pub async fn build(config: &Config, use_gh_cli: bool, resolve_patches: bool) -> Result<Build> {
     if config.repo.is_empty() {
         bail!(
             "You haven't specified a `repo` in your config, which can be for example:
@@ -64,28 +217,15 @@ pub async fn run(confirm: Option<Confirm>, use_gh_cli: bool) -> Result<()> {
         );
     }
 
-    let backed_up_files = config::backup::backup()?;
+    git::configure_commits(&config.committer);
 
-    let info = RemoteBranch {
-        remote: Remote {
-            repository_url: format!("https://github.com/{}.git", config.repo),
-            local_remote_alias: with_uuid(&config.repo),
-        },
-        branch: Branch {
-            upstream_branch_name: remote_branch.clone(),
-            local_branch_name: BranchName::try_new(with_uuid(remote_branch.as_ref()))
-                .expect("adding UUID to branch name does not invalidate it"),
-        },
-    };
-
-    github::add_remote_branch(&info, commit.as_ref())?;
+    let backed_up_files = config::backup::backup()?;
 
-    // we want to checkout the `branch` of `remote`
-    let branch = &info.branch.local_branch_name;
-    let remote = &info.remote.local_remote_alias;
+    let base = BaseBranch::fetch(config, use_gh_cli).await?;
+    let branch = &base.branch;
 
     let previous_branch = git::get_head_commit().map_err(|err| {
-        if let Err(err) = git::delete_remote_and_branch(remote, branch) {
+        if let Err(err) = base.remove() {
             err
         } else {
             anyhow!(
@@ -97,11 +237,25 @@ pub async fn run(confirm: Option<Confirm>, use_gh_cli: bool) -> Result<()> {
     })?;
 
     if let Err(err) = git::checkout(branch.as_ref()) {
-        git::delete_remote_and_branch(remote, branch)?;
-        bail!("Failed to checkout branch: {branch}, which belongs to remote {remote}\n{err}");
+        base.remove()?;
+        bail!("Failed to checkout branch: {branch}\n{err}");
     }
 
-    if config.pull_requests.is_empty() && config.branches.is_empty() {
+    let mut lockfile = Lockfile {
+        base: lock::Base {
+            repo: config.repo.clone(),
+            branch: base.name.to_string(),
+            commit: git::rev_parse("HEAD")
+                .ok()
+                .and_then(|commit| CommitId::try_new(commit).ok()),
+        },
+        ..Lockfile::default()
+    };
+
+    if config.pull_requests.is_empty()
+        && config.branches.is_empty()
+        && config.pull_request_queries.is_empty()
+    {
         log::warn!(
             "You haven't specified any pull requests or branches to fetch in your config, {}",
             format_url(
@@ -111,19 +265,331 @@ pub async fn run(confirm: Option<Confirm>, use_gh_cli: bool) -> Result<()> {
         );
     }
 
-    // Process pull requests
+    if let Err(err) = merge_entries(
+        config,
+        &mut lockfile,
+        &backed_up_files,
+        use_gh_cli,
+        resolve_patches,
+    )
+    .await
+    {
+        git::nuke_worktree()?;
+        git::checkout(&previous_branch)?;
+        base.remove()?;
+        return Err(err);
+    }
+
+    // downloaded patches were only fetched during the build, they are not regenerated
+    let regenerated = config::backup::changed(&backed_up_files)?
+        .into_iter()
+        .filter(|file| {
+            !config.patches.iter().any(|patch| {
+                patch.download.is_some()
+                    && patch::file_of(&patch.name) == config::PATH.join(file.filename())
+            })
+        })
+        .collect();
+
+    lockfile.write()?;
+
+    git::add(config::ROOT.as_str())?;
+    git::commit("restore configuration files")?;
+
+    let temporary_branch = with_uuid("temp-branch");
+
+    git::create_branch(&temporary_branch)?;
+
+    base.remove()?;
+
+    Ok(Build {
+        branch: temporary_branch,
+        lockfile,
+        previous_branch,
+        regenerated,
+    })
+}
+
+/// Merge the pull requests, branches and patches of the `config` into the current branch,
+/// running the hooks along the way
+///
+/// Fails if a hook fails with the `fail` policy
+async fn merge_entries(
+    config: &Config,
+    lockfile: &mut Lockfile,
+    backed_up_files: &[config::backup::FileBackup],
+    use_gh_cli: bool,
+    resolve_patches: bool,
+) -> Result<()> {
+    hooks::check_branch(&config.hooks.after_base, config.hooks.policy)?;
+
+    let pull_requests = expand_queries(config, lockfile, use_gh_cli).await;
+
+    merge_pull_requests(config, pull_requests, lockfile, use_gh_cli).await?;
+
+    merge_branches(config, lockfile, use_gh_cli).await?;
+
+    fs::create_dir_all(git::ROOT.join(config::ROOT.as_str())).map_err(|err| {
+        anyhow!(
+            "Could not create directory {}\n{err}",
+            config::ROOT.as_str()
+        )
+    })?;
+
+    config::backup::restore(backed_up_files)?;
+
+    apply_patches(config, lockfile, resolve_patches).await?;
+
+    hooks::check_branch(&config.hooks.after_all, config.hooks.policy)
+}
+
+/// Local copy of `remote-branch`, which everything is merged into
+#[derive(Debug)]
+pub struct BaseBranch {
+    /// Name of `remote-branch`, e.g. `master`
+    pub name: BranchName,
+    /// The local branch
+    pub branch: BranchName,
+    /// Remote which was added to fetch the branch, and has to be removed afterwards
+    temporary_remote: Option<String>,
+}
+
+impl BaseBranch {
+    /// Fetch `remote-branch` of the `config` into a new local branch
+    ///
+    /// Uses `base-remote`, or an existing remote which points at `repo`.
+    /// If there is no such remote, `repo` is fetched from GitHub.
+    ///
+    /// If `remote-branch` is omitted, uses the default branch of the repository
+    pub async fn fetch(config: &Config, use_gh_cli: bool) -> Result<Self> {
+        let remote = config
+            .base_remote
+            .clone()
+            .or_else(|| find_remote(&config.repo));
+
+        let (remote_branch, commit) =
+            if let Some(config::Branch { name, commit }) = &config.remote_branch {
+                (name.clone(), commit.clone())
+            } else {
+                let name = match &remote {
+                    Some(remote) => git::default_branch(remote)?,
+                    None => github::fetch_default_branch(&config.repo, use_gh_cli).await?,
+                };
+                log::info!(
+                    "Using the default branch {} of {}",
+                    name.as_ref().bright_blue(),
+                    config.repo
+                );
+                (name, None)
+            };
+
+        let branch = BranchName::try_new(with_uuid(remote_branch.as_ref()))
+            .expect("adding UUID to branch name does not invalidate it");
+
+        let Some(remote) = remote else {
+            let info = RemoteBranch {
+                remote: Remote {
+                    repository_url: format!("{}.git", github::repo_url(&config.repo)),
+                    local_remote_alias: with_uuid(&config.repo),
+                },
+                branch: Branch {
+                    upstream_branch_name: remote_branch.clone(),
+                    local_branch_name: branch,
+                },
+            };
+
+            github::add_remote_branch(&info, commit.as_ref())?;
+
+            return Ok(Self {
+                name: remote_branch,
+                branch: info.branch.local_branch_name,
+                temporary_remote: Some(info.remote.local_remote_alias),
+            });
+        };
+
+        log::info!(
+            "Fetching {remote_branch} from remote {}",
+            remote.bright_blue()
+        );
+
+        git::fetch_remote_branch(&branch, &remote_branch, &remote).map_err(|err| {
+            anyhow!("Failed to fetch branch {remote_branch} of remote {remote}:\n{err}")
+        })?;
+
+        let base = Self {
+            name: remote_branch,
+            branch,
+            temporary_remote: None,
+        };
+
+        if let Some(commit) = commit
+            && let Err(err) = git::reset_branch_to_commit(&base.branch, &commit)
+        {
+            base.remove()?;
+            bail!(
+                "Failed to find commit {} of branch {}. Are you sure the commit exists?\n{err}",
+                commit.as_ref(),
+                base.name
+            );
+        }
+
+        Ok(base)
+    }
+
+    /// Delete the local branch, and the remote if patchy added it
+    pub fn remove(&self) -> Result<()> {
+        match &self.temporary_remote {
+            Some(remote) => git::delete_remote_and_branch(remote, &self.branch),
+            None => git::delete_branch(&self.branch).map(drop),
+        }
+    }
+}
+
+/// Find a remote of the current repository which points at the GitHub `repo`
+fn find_remote(repo: &str) -> Option<String> {
+    git::remotes().ok()?.into_iter().find(|remote| {
+        git::get_remote_url(remote)
+            .is_ok_and(|url| RemoteUrl::parse(&url).is_ok_and(|url| url.is_github_repo(repo)))
+    })
+}
+
+/// A pull request to merge, with the query that matched it if it comes from `pull-request-queries`
+type QueuedPullRequest = (PullRequest, Option<String>);
+
+/// Expand `pull-request-queries` into the pull requests that they match
+///
+/// Returns the pull requests of the config, followed by the ones from queries
+/// along with the query that matched them
+async fn expand_queries(
+    config: &Config,
+    lockfile: &mut Lockfile,
+    use_gh_cli: bool,
+) -> Vec<QueuedPullRequest> {
+    let mut pull_requests = config
+        .pull_requests
+        .iter()
+        .cloned()
+        .map(|pull_request| (pull_request, None))
+        .collect::<Vec<_>>();
+
+    for query in &config.pull_request_queries {
+        let Ok(matches) = github::search_pull_requests(&config.repo, query, use_gh_cli)
+            .await
+            .inspect_err(|err| log::error!("{err}"))
+        else {
+            continue;
+        };
+
+        log::info!(
+            "Query {} matched {}",
+            query.bright_cyan(),
+            if matches.is_empty() {
+                "no pull requests".to_string()
+            } else {
+                matches
+                    .iter()
+                    .map(|pull_request| format!("#{}", pull_request.number).bright_blue())
+                    .join(", ")
+            }
+        );
+
+        lockfile.queries.push(lock::Query {
+            query: query.clone(),
+            pull_requests: matches
+                .iter()
+                .map(|pull_request| pull_request.number)
+                .collect(),
+        });
+
+        for pull_request in matches {
+            // pull requests in the config take precedence, e.g. if they are disabled or pinned
+            if pull_requests
+                .iter()
+                .any(|(existing, _)| existing.number == pull_request.number)
+            {
+                continue;
+            }
+
+            pull_requests.push((
+                PullRequest {
+                    number: pull_request.number,
+                    commit: None,
+                    options: EntryOptions {
+                        note: Some(format!("matched `{query}`")),
+                        ..EntryOptions::default()
+                    },
+                    after: Vec::new(),
+                },
+                Some(query.clone()),
+            ));
+        }
+    }
+
+    pull_requests
+}
+
+/// Merge each of the `pull_requests` into the current branch
+///
+/// Pull requests are merged after the pull requests in their `after`,
+/// and are skipped if any of those could not be merged
+async fn merge_pull_requests(
+    config: &Config,
+    pull_requests: Vec<QueuedPullRequest>,
+    lockfile: &mut Lockfile,
+    use_gh_cli: bool,
+) -> Result<()> {
+    let (ordered, cyclic) = order_by_dependencies(pull_requests);
+
     // TODO: make this concurrent, see https://users.rust-lang.org/t/processing-subprocesses-concurrently/79638/3
     // Git cannot handle multiple threads executing commands in the same repository,
     // so we can't use threads, but we can run processes in the background
-    for PullRequest {
-        number: pull_request,
-        commit,
-    } in &config.pull_requests
+    for (
+        PullRequest {
+            number: pull_request,
+            commit,
+            options,
+            after,
+        },
+        query,
+    ) in ordered
     {
-        // TODO: refactor this to not use such deep nesting
+        let mut record = lock::PullRequest {
+            number: pull_request,
+            title: None,
+            url: None,
+            author: None,
+            head: commit.clone(),
+            query,
+            outcome: Outcome::Failed,
+            reason: None,
+            hooks: None,
+        };
+
+        if !options.enabled {
+            log::info!(
+                "Skipping disabled pull request #{pull_request}{}",
+                format_note(options.note.as_deref())
+            );
+            record.outcome = Outcome::Disabled;
+            lockfile.pull_requests.push(record);
+            continue;
+        }
+
+        if let Some((outcome, reason)) = blocked_by(&after, lockfile) {
+            if outcome == Outcome::Disabled {
+                log::info!("Skipping pull request #{pull_request}, it {reason}");
+            } else {
+                log::warn!("Skipping pull request #{pull_request}, it {reason}");
+            }
+            record.outcome = outcome;
+            record.reason = Some(reason);
+            lockfile.pull_requests.push(record);
+            continue;
+        }
+
         let Ok((response, info)) = github::fetch_pull_request(
             &config.repo,
-            *pull_request,
+            pull_request,
             None,
             commit.as_ref(),
             use_gh_cli,
@@ -132,53 +598,214 @@ pub async fn run(confirm: Option<Confirm>, use_gh_cli: bool) -> Result<()> {
         .inspect_err(|err| {
             log::error!("failed to fetch branch from remote:\n{err}");
         }) else {
+            record.reason = Some("could not be fetched".to_string());
+            lockfile.pull_requests.push(record);
             continue;
         };
 
-        if let Err(err) =
-            merge_pull_request(&info, *pull_request, &response.title, &response.html_url)
-        {
+        record.title = Some(response.title.clone());
+        record.url = Some(response.html_url.clone());
+        record.author = Some(response.user.login.clone());
+        let head = commit.unwrap_or_else(|| response.head.sha.clone());
+        record.head = Some(head.clone());
+
+        let message = config.commit_message.pull_request(&[
+            ("number", &pull_request.to_string()),
+            ("title", &response.title),
+            ("url", &response.html_url),
+            ("author", &response.user.login),
+            ("author-email", &response.user.noreply_email()),
+            ("head", head.as_ref()),
+            ("base", response.base.sha.as_ref()),
+        ]);
+
+        let before = git::rev_parse("HEAD")?;
+
+        if let Err(err) = merge_pull_request(
+            &info,
+            pull_request,
+            &response.title,
+            &response.html_url,
+            options.merge_strategy.unwrap_or(config.merge_strategy),
+            &message,
+        ) {
             log::error!("failed to merge {pull_request}: {err}");
+            record.reason = Some("merge conflict".to_string());
+            lockfile.pull_requests.push(record);
             continue;
         }
 
         log::info!(
-            "Merged pull request {}",
-            format_pr(*pull_request, &response.title, &response.html_url),
+            "Merged pull request {}{}",
+            format_pr(pull_request, &response.title, &response.html_url),
+            format_note(options.note.as_deref())
+        );
+
+        (record.outcome, record.hooks) = hooks::check_entry(
+            &config.hooks.after_entry,
+            config.hooks.policy,
+            &format!("#{pull_request}"),
+            &before,
+        )?;
+        lockfile.pull_requests.push(record);
+    }
+
+    for (pull_request, query) in cyclic {
+        let reason = format!(
+            "is part of a dependency cycle: `after = [{}]`",
+            pull_request.after.iter().join(", ")
         );
+        log::error!(
+            "Skipping pull request #{}, it {reason}",
+            pull_request.number
+        );
+        lockfile.pull_requests.push(lock::PullRequest {
+            number: pull_request.number,
+            title: None,
+            url: None,
+            author: None,
+            head: pull_request.commit,
+            query,
+            outcome: Outcome::Skipped,
+            reason: Some(reason),
+            hooks: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// Why a pull request which is merged after the pull requests in `after` can't be merged,
+/// if any of them are in the `lockfile` but were not merged
+///
+/// The pull request is skipped if any of them failed. If they are only disabled,
+/// it is disabled as well, so it isn't reported as a failure
+fn blocked_by(after: &[PrNumber], lockfile: &Lockfile) -> Option<(Outcome, String)> {
+    let (disabled, unmerged): (Vec<_>, Vec<_>) = after
+        .iter()
+        .filter_map(|dependency| {
+            lockfile
+                .pull_requests
+                .iter()
+                .find(|merged| merged.number == *dependency)
+        })
+        .filter(|dependency| dependency.outcome != Outcome::Merged)
+        .partition(|dependency| dependency.outcome == Outcome::Disabled);
+
+    let numbers = |dependencies: Vec<&lock::PullRequest>| {
+        dependencies
+            .iter()
+            .map(|dependency| format!("#{}", dependency.number))
+            .join(", ")
+    };
+
+    if !unmerged.is_empty() {
+        Some((
+            Outcome::Skipped,
+            format!(
+                "depends on {}, which could not be merged",
+                numbers(unmerged)
+            ),
+        ))
+    } else if !disabled.is_empty() {
+        Some((
+            Outcome::Disabled,
+            format!("depends on {}, which is disabled", numbers(disabled)),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Order the `pull_requests` so that each one comes after the pull requests in its `after`,
+/// otherwise keeping their order
+///
+/// Returns the ordered pull requests, and the pull requests which can't be ordered
+/// because they are part of a dependency cycle, or depend on one
+fn order_by_dependencies(
+    mut pull_requests: Vec<QueuedPullRequest>,
+) -> (Vec<QueuedPullRequest>, Vec<QueuedPullRequest>) {
+    let numbers = pull_requests
+        .iter()
+        .map(|(pull_request, _)| pull_request.number)
+        .collect::<Vec<_>>();
+
+    for (pull_request, _) in &pull_requests {
+        for dependency in &pull_request.after {
+            if !numbers.contains(dependency) {
+                log::warn!(
+                    "Pull request #{} is merged after #{dependency}, which is not in the config",
+                    pull_request.number
+                );
+            }
+        }
+    }
+
+    let mut ordered: Vec<QueuedPullRequest> = Vec::with_capacity(pull_requests.len());
+
+    // take the first pull request whose dependencies have all been ordered,
+    // until there is none left
+    while let Some(index) = pull_requests.iter().position(|(pull_request, _)| {
+        pull_request.after.iter().all(|dependency| {
+            !numbers.contains(dependency)
+                || ordered
+                    .iter()
+                    .any(|(ordered, _)| ordered.number == *dependency)
+        })
+    }) {
+        ordered.push(pull_requests.remove(index));
     }
 
-    // Process branches
-    for remote in &config.branches {
-        let owner = &remote.owner;
-        let repo = &remote.repo;
-        let branch = &remote.branch;
-        let Ok((_, info)) = github::fetch_branch(remote, use_gh_cli)
+    (ordered, pull_requests)
+}
+
+/// Merge each of the `branches` of the `config` into the current branch
+async fn merge_branches(config: &Config, lockfile: &mut Lockfile, use_gh_cli: bool) -> Result<()> {
+    for BranchEntry { remote, options } in &config.branches {
+        let name = remote.name();
+
+        let mut record = lock::Branch {
+            remote: name.clone(),
+            commit: remote.commit().cloned(),
+            subject: None,
+            outcome: Outcome::Failed,
+            hooks: None,
+        };
+
+        if !options.enabled {
+            log::info!(
+                "Skipping disabled branch {name}{}",
+                format_note(options.note.as_deref())
+            );
+            record.outcome = Outcome::Disabled;
+            lockfile.branches.push(record);
+            continue;
+        }
+
+        let Ok(info) = github::fetch_branch_remote(remote, use_gh_cli)
             .await
             .inspect_err(|err| {
-                log::error!("failed to fetch branch {owner}/{repo}/{branch}: {err}");
+                log::error!("failed to fetch branch {name}: {err}");
             })
         else {
+            lockfile.branches.push(record);
             continue;
         };
 
-        if let Err(err) = merge(
+        record.subject = git::get_message_of_commit(info.branch.local_branch_name.as_ref())
+            .ok()
+            .and_then(|message| message.lines().next().map(ToString::to_string));
+
+        let before = git::rev_parse("HEAD")?;
+        let head = git::rev_parse(info.branch.local_branch_name.as_ref())?;
+
+        let merged = merge(
             &info.branch.local_branch_name,
             &info.branch.upstream_branch_name,
-        ) {
-            log::error!("{err}");
-        }
-
-        log::info!(
-            "Merged branch {}/{}/{} {}",
-            owner.as_ref().bright_blue(),
-            repo.as_ref().bright_blue(),
-            branch.as_ref().bright_blue(),
-            remote
-                .commit
-                .as_ref()
-                .map(|hash| format!("at commit {}", hash.as_ref().bright_yellow()))
-                .unwrap_or_default()
+            options.merge_strategy.unwrap_or(config.merge_strategy),
+            &config
+                .commit_message
+                .branch(&[("branch", &name), ("head", &head)]),
         );
 
         // Clean up the remote branch
@@ -188,119 +815,256 @@ pub async fn run(confirm: Option<Confirm>, use_gh_cli: bool) -> Result<()> {
         ) {
             log::warn!("Failed to clean up branch: {err}");
         }
-    }
-
-    if let Err(err) = fs::create_dir_all(git::ROOT.join(config::ROOT.as_str())) {
-        git::checkout(&previous_branch)?;
 
-        git::delete_remote_and_branch(
-            &info.remote.local_remote_alias,
-            &info.branch.local_branch_name,
-        )?;
+        if let Err(err) = merged {
+            log::error!("{err}");
+        } else {
+            log::info!(
+                "Merged branch {} {}{}",
+                name.bright_blue(),
+                remote
+                    .commit()
+                    .map(|hash| format!("at commit {}", hash.as_ref().bright_yellow()))
+                    .unwrap_or_default(),
+                format_note(options.note.as_deref())
+            );
+            (record.outcome, record.hooks) = hooks::check_entry(
+                &config.hooks.after_entry,
+                config.hooks.policy,
+                &name,
+                &before,
+            )?;
+        }
 
-        bail!(
-            "Could not create directory {}\n{err}",
-            config::ROOT.as_str()
-        );
+        lockfile.branches.push(record);
     }
 
-    config::backup::restore(&backed_up_files)?;
+    Ok(())
+}
 
-    for patch in config.patches {
-        let file_name = git::ROOT
-            .join(config::ROOT.as_str())
-            .join(format!("{patch}.patch"));
+/// Apply each of the `patches` of the `config` to the current branch
+///
+/// Patches with a URL are downloaded first, and skipped if their checksum does not match
+///
+/// If `resolve_patches`, patches with conflicts are left for the user to resolve,
+/// and regenerated from the resolution. Downloaded patches are never regenerated,
+/// as they would no longer match their checksum
+async fn apply_patches(
+    config: &Config,
+    lockfile: &mut Lockfile,
+    resolve_patches: bool,
+) -> Result<()> {
+    for patch in &config.patches {
+        let mut record = lock::Patch {
+            name: patch.to_string(),
+            subject: None,
+            outcome: Outcome::Failed,
+            hooks: None,
+        };
 
-        if !file_name.exists() {
-            log::error!("failed to find patch {patch}, skipping");
+        if !patch.enabled {
+            log::info!("Skipping disabled patch {patch}");
+            record.outcome = Outcome::Disabled;
+            lockfile.patches.push(record);
             continue;
         }
 
-        if let Err(err) = git::apply_patch(&file_name) {
+        if let Some(download) = &patch.download
+            && let Err(err) = patch::download(patch, download).await
+        {
+            log::error!("{err}");
+            lockfile.patches.push(record);
+            continue;
+        }
+
+        let files = match patch::files(&patch.name) {
+            Ok(Some(files)) => files,
+            Ok(None) => {
+                log::error!("failed to find patch {patch}, skipping");
+                lockfile.patches.push(record);
+                continue;
+            }
+            Err(err) => {
+                log::error!("failed to read patch {patch}, skipping\n{err}");
+                lockfile.patches.push(record);
+                continue;
+            }
+        };
+
+        // a series is applied completely or not at all
+        let before = git::rev_parse("HEAD")?;
+
+        if let Err(err) = files.iter().try_for_each(|file| {
+            apply_patch_file(patch, file, resolve_patches && patch.download.is_none())
+        }) {
+            git::reset_hard(&before)?;
             log::error!("failed to apply patch {patch}, skipping\n{err}");
+            lockfile.patches.push(record);
             continue;
         }
 
         let last_commit_message = git::last_commit_message()?;
+        let subject = last_commit_message.lines().next().unwrap_or_default();
 
-        log::info!(
-            "Applied patch {patch} {}",
-            last_commit_message
-                .lines()
-                .next()
-                .unwrap_or_default()
-                .bright_blue()
-                .italic()
-        );
+        log::info!("Applied patch {patch} {}", subject.bright_blue().italic());
+        record.subject = Some(subject.to_string());
+
+        (record.outcome, record.hooks) = hooks::check_entry(
+            &config.hooks.after_entry,
+            config.hooks.policy,
+            &patch.to_string(),
+            &before,
+        )?;
+        lockfile.patches.push(record);
     }
 
-    git::add(config::ROOT.as_str())?;
-    git::commit("restore configuration files")?;
+    Ok(())
+}
 
-    let temporary_branch = with_uuid("temp-branch");
+/// Apply the `file` of the `patch`, falling back to a three-way merge
+///
+/// If that fails too, reports the hunks and files which failed to apply. If `resolve_patches`,
+/// the conflict is left in place for the user to resolve, and the `file` is regenerated from
+/// the resolution. Otherwise, the conflict is discarded
+fn apply_patch_file(patch: &Patch, file: &Path, resolve_patches: bool) -> Result<()> {
+    let format = patch::Format::of(file)?;
+    let before = git::rev_parse("HEAD")?;
 
-    git::create_branch(&temporary_branch)?;
+    let (hunks, files) = match format.apply(file, patch)? {
+        git::Applied::Cleanly => return Ok(()),
+        git::Applied::ThreeWay => {
+            log::warn!(
+                "patch {patch} is out of date, applied {} with a three-way merge",
+                file.display()
+            );
+            return Ok(());
+        }
+        git::Applied::Empty => {
+            log::warn!(
+                "{} is already applied, upstream might contain patch {patch} now",
+                file.display()
+            );
+            return Ok(());
+        }
+        git::Applied::Conflict { hunks, files } => (hunks, files),
+    };
 
-    git::delete_remote_and_branch(
-        &info.remote.local_remote_alias,
-        &info.branch.local_branch_name,
-    )?;
+    log::error!(
+        "failed to apply {}:\n{}{}",
+        file.display(),
+        hunks
+            .iter()
+            .map(|hunk| format!("  {}\n", hunk.bright_red()))
+            .join(""),
+        if files.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "conflicts in:\n{}",
+                files
+                    .iter()
+                    .map(|file| format!("  {}\n", file.bright_yellow()))
+                    .join("")
+            )
+        }
+    );
 
-    let overwrite_branch = match confirm {
-        Some(Confirm::Yes) => true,
-        Some(Confirm::No) => false,
-        None => confirm_prompt!(
-            "Overwrite branch {}? This is irreversible.",
-            config.local_branch.as_ref().cyan()
-        ),
-    };
+    if !resolve_patches
+        || !confirm_prompt!(
+            "Resolve the conflicts in {} and stage them with `git add`. Continue?",
+            git::ROOT.display()
+        )
+    {
+        format.abort()?;
+        bail!("patch {patch} does not apply");
+    }
 
-    if overwrite_branch {
-        git::rename_branch(&temporary_branch, config.local_branch.as_ref())?;
-        log::info!("Success!");
-        return Ok(());
+    if let Err(err) = format.resume(patch) {
+        format.abort()?;
+        bail!(
+            "failed to commit the resolution of {}\n{err}",
+            file.display()
+        );
     }
 
-    let overwrite_command = format!(
-        "git branch --move --force {temporary_branch} {}",
-        config.local_branch
-    );
+    fs::write(file, format!("{}\n", format.regenerate(&before)?))?;
+
     log::info!(
-        "You can still manually overwrite {} with:\n  {overwrite_command}\n",
-        config.local_branch.as_ref().cyan(),
+        "Regenerated {} from your resolution",
+        file.display().to_string().bright_blue()
     );
 
     Ok(())
 }
 
-/// Create a merge commit that merges the `other_branch` into `current_branch`
+/// Merge `current_branch` into patchy's branch using the given `strategy`
+///
+/// The commit that is created has the `message`. The `rebase` strategy keeps the commits
+/// of `current_branch` with their own messages, and only appends the trailers of the `message`
 pub fn merge(
     current_branch: &BranchName,
     other_branch: &BranchName,
+    strategy: MergeStrategy,
+    message: &Message,
 ) -> Result<String, anyhow::Error> {
-    log::debug!("Merging branch {current_branch}");
+    log::debug!("Merging branch {current_branch} using the {strategy:?} strategy");
 
-    if let Err(err) = git::merge(current_branch.as_ref()) {
-        git::nuke_worktree()?;
-        bail!("failed to merge {other_branch}\n{err}");
-    }
+    match strategy {
+        MergeStrategy::Squash => {
+            if let Err(err) = git::merge(current_branch.as_ref()) {
+                git::nuke_worktree()?;
+                bail!("failed to merge {other_branch}\n{err}");
+            }
 
-    // --squash will NOT commit anything. So we need to make the commit it manually
-    git::commit(&format!("Merge {current_branch}"))?;
+            // --squash will NOT commit anything. So we need to make the commit it manually
+            git::squash_commit(&message.to_string())?;
+        }
+        MergeStrategy::Merge => {
+            if let Err(err) = git::merge_commit(current_branch.as_ref(), &message.to_string()) {
+                if let Err(abort_err) = git::abort_merge() {
+                    log::error!("failed to abort the merge of {other_branch}:\n{abort_err}");
+                }
+                bail!("failed to merge {other_branch}\n{err}");
+            }
+        }
+        MergeStrategy::Rebase => {
+            let patchy_branch = git::get_head_commit()?;
+            let head = git::rev_parse("HEAD")?;
+
+            // rebasing checks out `current_branch`, so we fast-forward
+            // patchy's branch to it afterwards
+            if let Err(err) = git::rebase(&head, current_branch.as_ref(), &message.trailers) {
+                if let Err(abort_err) = git::abort_rebase() {
+                    log::error!("failed to abort the rebase of {other_branch}:\n{abort_err}");
+                }
+                if let Err(checkout_err) = git::checkout(&patchy_branch) {
+                    log::error!("failed to check out {patchy_branch}:\n{checkout_err}");
+                }
+                bail!("failed to rebase {other_branch}\n{err}");
+            }
+
+            git::checkout(&patchy_branch)?;
+            git::fast_forward(current_branch.as_ref())?;
+        }
+    }
 
     Ok(format!("Merged {other_branch} successfully"))
 }
 
-/// Merge the `pull_request` into patchy's branch
+/// Merge the `pull_request` into patchy's branch, creating a commit with the `message`
 pub fn merge_pull_request(
     info: &RemoteBranch,
     pull_request: PrNumber,
     pr_title: &str,
     pr_url: &str,
+    strategy: MergeStrategy,
+    message: &Message,
 ) -> Result<()> {
     merge(
         &info.branch.local_branch_name,
         &info.branch.upstream_branch_name,
+        strategy,
+        message,
     )
     .map_err(|err| {
         let pr = format_pr(pull_request, pr_title, pr_url);
@@ -321,13 +1085,6 @@ pub fn merge_pull_request(
         )
     })?;
 
-    if git::is_worktree_dirty() {
-        git::commit(&format!(
-            "auto-merge pull request {}",
-            &pr_url.replace("github.com", "redirect.github.com")
-        ))?;
-    }
-
     git::delete_remote_and_branch(
         &info.remote.local_remote_alias,
         &info.branch.local_branch_name,
@@ -335,3 +1092,95 @@ pub fn merge_pull_request(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_pull_requests_by_dependencies() {
+        let pull_request = |number: u32, after: &[u32]| {
+            (
+                PullRequest {
+                    number: number.try_into().unwrap(),
+                    commit: None,
+                    options: EntryOptions::default(),
+                    after: after
+                        .iter()
+                        .map(|number| (*number).try_into().unwrap())
+                        .collect(),
+                },
+                None,
+            )
+        };
+        let numbers = |pull_requests: Vec<QueuedPullRequest>| {
+            pull_requests
+                .into_iter()
+                .map(|(pull_request, _)| pull_request.number.into_inner())
+                .collect::<Vec<_>>()
+        };
+
+        let (ordered, cyclic) = order_by_dependencies(vec![
+            pull_request(1, &[3]),
+            pull_request(2, &[]),
+            pull_request(3, &[2, 99]),
+            pull_request(4, &[5]),
+            pull_request(5, &[4]),
+            pull_request(6, &[5]),
+            pull_request(7, &[]),
+        ]);
+
+        assert_eq!(
+            numbers(ordered),
+            [2, 3, 1, 7],
+            "dependencies come first, otherwise the order is kept"
+        );
+        assert_eq!(numbers(cyclic), [4, 5, 6]);
+    }
+
+    #[test]
+    fn blocked_by_dependencies() {
+        let record = |number: u32, outcome| lock::PullRequest {
+            number: number.try_into().unwrap(),
+            title: None,
+            url: None,
+            author: None,
+            head: None,
+            query: None,
+            outcome,
+            reason: None,
+            hooks: None,
+        };
+        let lockfile = Lockfile {
+            pull_requests: vec![
+                record(1, Outcome::Merged),
+                record(2, Outcome::Disabled),
+                record(3, Outcome::Failed),
+            ],
+            ..Lockfile::default()
+        };
+        let blocked_by = |after: &[u32]| {
+            let after = after
+                .iter()
+                .map(|number| (*number).try_into().unwrap())
+                .collect::<Vec<_>>();
+            blocked_by(&after, &lockfile)
+        };
+
+        assert_eq!(blocked_by(&[1, 99]), None);
+        assert_eq!(
+            blocked_by(&[1, 2]),
+            Some((
+                Outcome::Disabled,
+                "depends on #2, which is disabled".to_string()
+            ))
+        );
+        assert_eq!(
+            blocked_by(&[2, 3]),
+            Some((
+                Outcome::Skipped,
+                "depends on #3, which could not be merged".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! `status` subcommand
+
+use std::fs;
+
+use anyhow::bail;
+use colored::Colorize as _;
+
+use crate::commands::run::BaseBranch;
+use crate::config::{CommitId, Config, PrNumber};
+use crate::lock::{Lockfile, Outcome};
+use crate::utils::short;
+use crate::{git, github, patch};
+
+/// Format of `git log` which lists the `Patchy-PR` and `Patchy-Head` trailers of each commit
+const TRAILERS_FORMAT: &str = "%(trailers:key=Patchy-PR,valueonly,separator=%x2C)%x09%(trailers:key=Patchy-Head,valueonly,separator=%x2C)";
+
+/// A pull request which is on `local-branch`
+#[derive(Debug, PartialEq, Eq)]
+struct Merged {
+    /// Number of the pull request
+    number: PrNumber,
+    /// Commit of the pull request which was merged, if it is known
+    head: Option<CommitId>,
+}
+
+/// Report how `local-branch` relates to the config: how far its base is behind upstream,
+/// which pull requests have new commits, which entries are missing and which patches changed
+///
+/// If `profile` is given, that profile of the config is used
+#[expect(
+    clippy::print_stdout,
+    reason = "the status is the output of the command"
+)]
+pub async fn status(profile: Option<String>, use_gh_cli: bool) -> anyhow::Result<()> {
+    let config = Config::read_with_profile(profile.as_deref())?;
+
+    let use_gh_cli = use_gh_cli || config.use_gh_cli;
+    let local_branch = config.local_branch.as_ref();
+
+    if git::rev_parse(local_branch).is_err() {
+        bail!("{local_branch} does not exist yet, create it with `patchy run`");
+    }
+
+    let lockfile = Lockfile::read_from(local_branch)?;
+    let base_commit = lockfile
+        .as_ref()
+        .and_then(|lockfile| lockfile.base.commit.clone());
+
+    let mut up_to_date = true;
+
+    // base
+
+    match &base_commit {
+        Some(base_commit) => {
+            print!(
+                "{} is based on {} {}",
+                local_branch.bright_cyan(),
+                config.repo.bright_blue(),
+                short(base_commit.as_ref()).bright_yellow()
+            );
+
+            match commits_behind(&config, base_commit, use_gh_cli).await {
+                Ok((_, 0)) => println!(),
+                Ok((branch, behind)) => {
+                    up_to_date = false;
+                    println!(
+                        ", {}",
+                        format!(
+                            "{behind} {} behind {branch}",
+                            if behind == 1 { "commit" } else { "commits" }
+                        )
+                        .bright_yellow()
+                    );
+                }
+                Err(err) => {
+                    println!();
+                    log::warn!("Could not fetch the base:\n{err}");
+                }
+            }
+        }
+        None => println!(
+            "{} has no {}, so its base is unknown",
+            local_branch.bright_cyan(),
+            crate::lock::FILE
+        ),
+    }
+
+    // pull requests
+
+    let range = base_commit.as_ref().map_or_else(
+        || local_branch.to_string(),
+        |base_commit| format!("{base_commit}..{local_branch}"),
+    );
+    let mut merged = parse_trailers(&git::log(&range, TRAILERS_FORMAT)?);
+
+    // pull requests merged with `trailers = []`, or by an older patchy which did not add trailers to
+    // rebased commits, are only recorded in the lockfile
+    for record in lockfile
+        .iter()
+        .flat_map(|lockfile| &lockfile.pull_requests)
+        .filter(|record| record.outcome == Outcome::Merged)
+    {
+        if !merged.iter().any(|merged| merged.number == record.number) {
+            merged.push(Merged {
+                number: record.number,
+                head: record.head.clone(),
+            });
+        }
+    }
+
+    let mut moved = Vec::new();
+    let mut missing = Vec::new();
+
+    for pull_request in config
+        .pull_requests
+        .iter()
+        .filter(|pull_request| pull_request.options.enabled)
+    {
+        let Some(Merged { head, .. }) = merged
+            .iter()
+            .find(|merged| merged.number == pull_request.number)
+        else {
+            missing.push(format!("#{}", pull_request.number));
+            continue;
+        };
+
+        let expected = match &pull_request.commit {
+            Some(commit) => commit.clone(),
+            None => {
+                match github::fetch_pr_data(&config.repo, pull_request.number, use_gh_cli).await {
+                    Ok(response) => response.head.sha,
+                    Err(err) => {
+                        log::warn!("{err}");
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if head
+            .as_ref()
+            .is_some_and(|head| !is_same_commit(head.as_ref(), expected.as_ref()))
+        {
+            moved.push(format!(
+                "#{}  {} -> {}",
+                pull_request.number,
+                head.as_ref().map_or("", |head| short(head.as_ref())),
+                short(expected.as_ref())
+            ));
+        }
+    }
+
+    let queried = lockfile
+        .iter()
+        .flat_map(|lockfile| &lockfile.pull_requests)
+        .filter(|record| record.query.is_some())
+        .map(|record| record.number)
+        .collect::<Vec<_>>();
+
+    let removed = merged
+        .iter()
+        .filter(|merged| {
+            !queried.contains(&merged.number)
+                && !config
+                    .pull_requests
+                    .iter()
+                    .any(|pull_request| pull_request.number == merged.number)
+        })
+        .map(|merged| format!("#{}", merged.number))
+        .collect::<Vec<_>>();
+
+    // branches
+
+    for branch in config
+        .branches
+        .iter()
+        .filter(|branch| branch.options.enabled)
+    {
+        let name = branch.remote.name();
+        let is_merged = lockfile
+            .iter()
+            .flat_map(|lockfile| &lockfile.branches)
+            .any(|record| record.remote == name && record.outcome == Outcome::Merged);
+
+        if !is_merged {
+            missing.push(name);
+        }
+    }
+
+    // patches
+
+    let mut changed = Vec::new();
+
+    // downloaded patches are pinned by their `sha256`, so they can only change with the config,
+    // and they might not have been downloaded yet
+    for patch in config
+        .patches
+        .iter()
+        .filter(|patch| patch.enabled && patch.download.is_none())
+    {
+        let Some(files) = patch::files(&patch.name)? else {
+            missing.push(format!("patch {patch}"));
+            continue;
+        };
+
+        for file in files {
+            let relative = file
+                .strip_prefix(&*git::ROOT)
+                .unwrap_or(&file)
+                .to_string_lossy()
+                .replace('\\', "/");
+
+            let committed = git::show_file_bytes(local_branch, &relative).ok();
+
+            if committed != Some(fs::read(&file)?) {
+                changed.push(relative);
+            }
+        }
+    }
+
+    for (title, entries) in [
+        ("pull requests with new commits:".to_string(), moved),
+        (format!("not on {local_branch}:"), missing),
+        (
+            format!("on {local_branch}, but not in the config:"),
+            removed,
+        ),
+        ("patches changed since the last run:".to_string(), changed),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+
+        up_to_date = false;
+        println!("\n{}", title.bright_green());
+        for entry in entries {
+            println!("  {}", entry.bright_blue());
+        }
+    }
+
+    if up_to_date {
+        println!(
+            "\n{} is up to date with the config",
+            local_branch.bright_cyan()
+        );
+    } else {
+        println!(
+            "\nRun {} to update {}",
+            "patchy run".bright_cyan(),
+            local_branch.bright_cyan()
+        );
+    }
+
+    Ok(())
+}
+
+/// Number of commits that `remote-branch` of the `config` has after the `base_commit`,
+/// and the name of the branch
+async fn commits_behind(
+    config: &Config,
+    base_commit: &CommitId,
+    use_gh_cli: bool,
+) -> anyhow::Result<(String, usize)> {
+    let base = BaseBranch::fetch(config, use_gh_cli).await?;
+    let behind = git::count_commits(&format!("{base_commit}..{}", base.branch));
+    base.remove()?;
+
+    Ok((base.name.to_string(), behind?))
+}
+
+/// Pull requests in the output of `git log` with the [`TRAILERS_FORMAT`]
+fn parse_trailers(log: &str) -> Vec<Merged> {
+    log.lines()
+        .filter_map(|line| {
+            let (number, head) = line.split_once('\t')?;
+            Some(Merged {
+                number: number.trim().parse().ok()?,
+                head: head.trim().parse().ok(),
+            })
+        })
+        .collect()
+}
+
+/// `true` if both hashes refer to the same commit, even if one is abbreviated
+fn is_same_commit(a: &str, b: &str) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pull_request_trailers() {
+        let log = "12254\tdeadbeef\n\t\n454\t\n";
+
+        assert_eq!(
+            parse_trailers(log),
+            [
+                Merged {
+                    number: 12254.try_into().unwrap(),
+                    head: Some("deadbeef".try_into().unwrap()),
+                },
+                Merged {
+                    number: 454.try_into().unwrap(),
+                    head: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,639 @@
+//! Edit patchy's config file in place, preserving its formatting and comments
+
+use std::fs;
+
+use anyhow::{Result, anyhow, bail};
+use serde::de::DeserializeOwned;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Value};
+
+use super::{
+    BranchEntry, BranchName, BranchRemote, Config, EntryOptions, FILE, FILE_PATH, Patch, PatchName,
+    PrNumber, PullRequest, ROOT,
+};
+
+/// A list of entries in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum List {
+    /// `pull-requests`
+    PullRequests,
+    /// `branches`
+    Branches,
+    /// `patches`
+    Patches,
+}
+
+impl List {
+    /// Key of this list in the config file
+    pub const fn key(self) -> &'static str {
+        match self {
+            Self::PullRequests => "pull-requests",
+            Self::Branches => "branches",
+            Self::Patches => "patches",
+        }
+    }
+}
+
+/// Patchy's config file, parsed with a format-preserving TOML parser
+#[derive(Debug)]
+pub struct ConfigFile {
+    /// The parsed document
+    document: DocumentMut,
+}
+
+impl ConfigFile {
+    /// Open patchy's config file
+    pub fn open() -> Result<Self> {
+        Self::parse(&super::read_file()?)
+    }
+
+    /// Parse contents of a config file
+    pub fn parse(contents: &str) -> Result<Self> {
+        contents
+            .parse::<DocumentMut>()
+            .map(|document| Self { document })
+            .map_err(|err| anyhow!("Could not parse `{}/{FILE}`:\n{err}", ROOT.as_str()))
+    }
+
+    /// Write the config file back to disk
+    ///
+    /// Fails without writing anything if the edited config is no longer valid
+    pub fn save(&self) -> Result<()> {
+        let contents = self.document.to_string();
+
+        Config::resolve(&contents)
+            .map_err(|err| anyhow!("refusing to write an invalid config:\n{err}"))?;
+
+        fs::write(&*FILE_PATH, contents)?;
+
+        Ok(())
+    }
+
+    /// Add a pull request to the end of `pull-requests`
+    pub fn add_pull_request(&mut self, pull_request: &PullRequest) -> Result<()> {
+        if self
+            .entries::<PullRequest>(List::PullRequests)?
+            .any(|entry| entry.number == pull_request.number)
+        {
+            bail!(
+                "pull request #{} is already in `{}`",
+                pull_request.number,
+                List::PullRequests.key()
+            );
+        }
+
+        self.push(List::PullRequests, pull_request_value(pull_request))
+    }
+
+    /// Add a branch to the end of `branches`
+    ///
+    /// A branch is already in `branches` if it is there with or without the name
+    /// of `default_branch`, e.g. as `owner/repo` or as `owner/repo/master`
+    pub fn add_branch(
+        &mut self,
+        branch: &BranchEntry,
+        default_branch: Option<&BranchName>,
+    ) -> Result<()> {
+        if self
+            .entries::<BranchEntry>(List::Branches)?
+            .any(|entry| entry.remote.is_same_branch(&branch.remote, default_branch))
+        {
+            bail!(
+                "branch {} is already in `{}`",
+                branch.remote,
+                List::Branches.key()
+            );
+        }
+
+        self.push(List::Branches, branch_value(branch))
+    }
+
+    /// Add a patch to the end of `patches`
+    pub fn add_patch(&mut self, patch: &PatchName) -> Result<()> {
+        if self
+            .entries::<Patch>(List::Patches)?
+            .any(|entry| &entry.name == patch)
+        {
+            bail!("patch {patch} is already in `{}`", List::Patches.key());
+        }
+
+        self.push(List::Patches, patch.to_string().into())
+    }
+
+    /// Remove the pull request from `pull-requests`
+    pub fn remove_pull_request(&mut self, number: PrNumber) -> Result<()> {
+        self.remove::<PullRequest>(List::PullRequests, |entry| entry.number == number)
+            .then_some(())
+            .ok_or_else(|| anyhow!("pull request #{number} is not in `pull-requests`"))
+    }
+
+    /// Remove the branch from `branches`
+    ///
+    /// It is removed with or without the name of `default_branch`,
+    /// e.g. `owner/repo/master` removes `owner/repo` if `master` is the default branch
+    pub fn remove_branch(
+        &mut self,
+        remote: &BranchRemote,
+        default_branch: Option<&BranchName>,
+    ) -> Result<()> {
+        self.remove::<BranchEntry>(List::Branches, |entry| {
+            entry.remote.is_same_branch(remote, default_branch)
+        })
+        .then_some(())
+        .ok_or_else(|| anyhow!("branch {remote} is not in `branches`"))
+    }
+
+    /// Remove the patch from `patches`
+    pub fn remove_patch(&mut self, patch: &PatchName) -> Result<()> {
+        self.remove::<Patch>(List::Patches, |entry| &entry.name == patch)
+            .then_some(())
+            .ok_or_else(|| anyhow!("patch {patch} is not in `patches`"))
+    }
+
+    /// Enable or disable the pull request in `pull-requests` of the `profile`,
+    /// or of the shared config if the profile does not override it
+    pub fn set_pull_request_enabled(
+        &mut self,
+        profile: Option<&str>,
+        number: PrNumber,
+        enabled: bool,
+    ) -> Result<()> {
+        self.update_in_profile::<PullRequest>(
+            profile,
+            List::PullRequests,
+            |entry| entry.number == number,
+            |mut entry| {
+                entry.options.enabled = enabled;
+                pull_request_value(&entry)
+            },
+        )
+        .then_some(())
+        .ok_or_else(|| anyhow!("pull request #{number} is not in `pull-requests`"))
+    }
+
+    /// Enable or disable the branch in `branches` of the `profile`,
+    /// or of the shared config if the profile does not override it
+    ///
+    /// `remote` must be spelled as it is in the config, e.g. `owner/repo` and
+    /// `owner/repo/master` are different branches
+    pub fn set_branch_enabled(
+        &mut self,
+        profile: Option<&str>,
+        remote: &BranchRemote,
+        enabled: bool,
+    ) -> Result<()> {
+        self.update_in_profile::<BranchEntry>(
+            profile,
+            List::Branches,
+            |entry| entry.remote.is_same_branch(remote, None),
+            |mut entry| {
+                entry.options.enabled = enabled;
+                branch_value(&entry)
+            },
+        )
+        .then_some(())
+        .ok_or_else(|| anyhow!("branch {remote} is not in `branches`"))
+    }
+
+    /// Enable or disable the patch in `patches` of the `profile`,
+    /// or of the shared config if the profile does not override it
+    pub fn set_patch_enabled(
+        &mut self,
+        profile: Option<&str>,
+        patch: &PatchName,
+        enabled: bool,
+    ) -> Result<()> {
+        self.update_in_profile::<Patch>(
+            profile,
+            List::Patches,
+            |entry| &entry.name == patch,
+            |mut entry| {
+                entry.enabled = enabled;
+                patch_value(&entry)
+            },
+        )
+        .then_some(())
+        .ok_or_else(|| anyhow!("patch {patch} is not in `patches`"))
+    }
+
+    /// Parse every element of the `list` which is a valid entry of type `T`
+    fn entries<T: DeserializeOwned>(&self, list: List) -> Result<impl Iterator<Item = T>> {
+        Ok(self
+            .array(list)?
+            .into_iter()
+            .flat_map(Array::iter)
+            .filter_map(parse_value))
+    }
+
+    /// Append `value` to the `list`, matching the formatting of the existing elements
+    fn push(&mut self, list: List, mut value: Value) -> Result<()> {
+        let array = self
+            .document
+            .entry(list.key())
+            .or_insert_with(|| Item::Value(Value::Array(Array::new())))
+            .as_array_mut()
+            .ok_or_else(|| anyhow!("`{}` is not an array", list.key()))?;
+
+        // put the new element on its own line if the last one is,
+        // without copying any comments that are above it
+        if let Some(prefix) = array
+            .iter()
+            .last()
+            .and_then(|last| last.decor().prefix())
+            .and_then(|prefix| prefix.as_str())
+        {
+            let indent = prefix
+                .rsplit_once('\n')
+                .map_or(prefix, |(_, indent)| indent);
+            let newline = if prefix.contains('\n') { "\n" } else { "" };
+            value.decor_mut().set_prefix(format!("{newline}{indent}"));
+        }
+
+        array.push_formatted(value);
+
+        Ok(())
+    }
+
+    /// Remove every element of the `list` for which `predicate` returns `true`
+    ///
+    /// Returns `true` if anything was removed
+    fn remove<T: DeserializeOwned>(&mut self, list: List, predicate: impl Fn(&T) -> bool) -> bool {
+        let Some(array) = self
+            .document
+            .get_mut(list.key())
+            .and_then(Item::as_array_mut)
+        else {
+            return false;
+        };
+
+        let len = array.len();
+        array.retain(|value| !parse_value(value).is_some_and(|entry| predicate(&entry)));
+        array.len() != len
+    }
+
+    /// [`ConfigFile::update`] the `list` of the `profile`, since its entries replace those of the
+    /// shared config. If the profile has no matching element, updates the shared `list` instead
+    fn update_in_profile<T: DeserializeOwned>(
+        &mut self,
+        profile: Option<&str>,
+        list: List,
+        predicate: impl Fn(&T) -> bool,
+        update: impl Fn(T) -> Value,
+    ) -> bool {
+        profile.is_some_and(|profile| self.update(Some(profile), list, &predicate, &update))
+            || self.update(None, list, predicate, update)
+    }
+
+    /// Replace every element of the `list` of the `profile`, or of the shared config, for which
+    /// `predicate` returns `true` with the value that `update` makes of it, keeping its comments
+    ///
+    /// Returns `true` if anything was replaced
+    fn update<T: DeserializeOwned>(
+        &mut self,
+        profile: Option<&str>,
+        list: List,
+        predicate: impl Fn(&T) -> bool,
+        update: impl Fn(T) -> Value,
+    ) -> bool {
+        let item = match profile {
+            Some(profile) => self
+                .document
+                .get_mut("profiles")
+                .and_then(|profiles| profiles.get_mut(profile))
+                .and_then(|profile| profile.get_mut(list.key())),
+            None => self.document.get_mut(list.key()),
+        };
+        let Some(array) = item.and_then(Item::as_array_mut) else {
+            return false;
+        };
+
+        let mut updated = false;
+
+        for value in array.iter_mut() {
+            if let Some(entry) = parse_value::<T>(value).filter(|entry| predicate(entry)) {
+                let decor = value.decor().clone();
+                *value = update(entry);
+                *value.decor_mut() = decor;
+                updated = true;
+            }
+        }
+
+        updated
+    }
+
+    /// The array of the `list`, if it exists
+    fn array(&self, list: List) -> Result<Option<&Array>> {
+        self.document
+            .get(list.key())
+            .map(|item| {
+                item.as_array()
+                    .ok_or_else(|| anyhow!("`{}` is not an array", list.key()))
+            })
+            .transpose()
+    }
+}
+
+impl std::fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.document)
+    }
+}
+
+/// Parse a TOML value as an entry of type `T`
+fn parse_value<T: DeserializeOwned>(value: &Value) -> Option<T> {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    T::deserialize(toml::de::ValueDeserializer::new(&value.to_string())).ok()
+}
+
+/// The `pull_request` as an element of `pull-requests`, which is a string unless it needs options
+fn pull_request_value(pull_request: &PullRequest) -> Value {
+    if pull_request.options == EntryOptions::default() && pull_request.after.is_empty() {
+        return Value::from(pull_request.to_string());
+    }
+
+    let mut table = InlineTable::new();
+    table.insert("number", i64::from(pull_request.number.into_inner()).into());
+    if let Some(commit) = &pull_request.commit {
+        table.insert("commit", commit.as_ref().into());
+    }
+    insert_options(&mut table, &pull_request.options);
+    if !pull_request.after.is_empty() {
+        table.insert(
+            "after",
+            pull_request
+                .after
+                .iter()
+                .map(|number| i64::from(number.into_inner()))
+                .collect::<Array>()
+                .into(),
+        );
+    }
+    Value::InlineTable(table)
+}
+
+/// The `branch` as an element of `branches`, which is a string unless it needs options
+fn branch_value(branch: &BranchEntry) -> Value {
+    if branch.options == EntryOptions::default() {
+        return Value::from(branch.remote.to_string());
+    }
+
+    let mut table = InlineTable::new();
+    match &branch.remote {
+        BranchRemote::GitHub(remote) => {
+            table.insert("remote", remote.to_string().into());
+        }
+        BranchRemote::Url(url) => {
+            table.insert("url", url.url.as_str().into());
+            if let Some(branch) = &url.branch {
+                table.insert("branch", branch.as_ref().into());
+            }
+            if let Some(commit) = &url.commit {
+                table.insert("commit", commit.as_ref().into());
+            }
+        }
+    }
+    insert_options(&mut table, &branch.options);
+    Value::InlineTable(table)
+}
+
+/// The `patch` as an element of `patches`, which is a string unless it needs options
+fn patch_value(patch: &Patch) -> Value {
+    let Patch {
+        name,
+        message,
+        author,
+        download,
+        enabled,
+    } = patch;
+
+    if message.is_none() && author.is_none() && download.is_none() && *enabled {
+        return Value::from(name.to_string());
+    }
+
+    let mut table = InlineTable::new();
+    table.insert("name", name.to_string().into());
+    if let Some(message) = message {
+        table.insert("message", message.as_str().into());
+    }
+    if let Some(author) = author {
+        table.insert("author", author.as_str().into());
+    }
+    if let Some(download) = download {
+        table.insert("url", download.url.as_str().into());
+        table.insert("sha256", download.sha256.as_str().into());
+    }
+    if !enabled {
+        table.insert("enabled", false.into());
+    }
+    Value::InlineTable(table)
+}
+
+/// Insert the non-default `options` into an inline table
+fn insert_options(table: &mut InlineTable, options: &EntryOptions) {
+    if let Some(note) = &options.note {
+        table.insert("note", note.as_str().into());
+    }
+    if !options.enabled {
+        table.insert("enabled", false.into());
+    }
+    if let Some(merge_strategy) = options.merge_strategy {
+        table.insert("merge-strategy", merge_strategy.as_str().into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+
+pull-requests = [
+  # syntax highlighting for nginx files
+  "12309",
+  # adds file explorer
+  { number = 11285, note = "file explorer" },
+]
+
+# no branches yet
+branches = []
+"#;
+
+    #[test]
+    fn add_entries() {
+        let mut file = ConfigFile::parse(CONFIG).unwrap();
+
+        file.add_pull_request(&PullRequest {
+            number: 8908.try_into().unwrap(),
+            commit: Some("a1b2c3".try_into().unwrap()),
+            options: EntryOptions::default(),
+            after: vec![],
+        })
+        .unwrap();
+        file.add_pull_request(&PullRequest {
+            number: 454.try_into().unwrap(),
+            commit: None,
+            options: EntryOptions {
+                note: Some("global status line".to_string()),
+                ..EntryOptions::default()
+            },
+            after: vec![8908.try_into().unwrap()],
+        })
+        .unwrap();
+        file.add_branch(&"owner/repo/feat".parse().unwrap(), None)
+            .unwrap();
+        file.add_patch(&"remove-tab".try_into().unwrap()).unwrap();
+
+        pretty_assertions::assert_eq!(
+            file.to_string(),
+            r#"repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+
+pull-requests = [
+  # syntax highlighting for nginx files
+  "12309",
+  # adds file explorer
+  { number = 11285, note = "file explorer" },
+  "8908 @ a1b2c3",
+  { number = 454, note = "global status line", after = [8908] },
+]
+
+# no branches yet
+branches = ["owner/repo/feat"]
+patches = ["remove-tab"]
+"#
+        );
+    }
+
+    #[test]
+    fn add_duplicate() {
+        let mut file = ConfigFile::parse(CONFIG).unwrap();
+
+        file.add_pull_request(&"11285 @ a1b2c3".parse().unwrap())
+            .unwrap_err();
+
+        let master = "master".try_into().unwrap();
+        file.add_branch(&"owner/repo".parse().unwrap(), None)
+            .unwrap();
+        file.add_branch(&"owner/repo/master".parse().unwrap(), Some(&master))
+            .expect_err("`owner/repo` is the default branch `master`");
+    }
+
+    #[test]
+    fn remove_default_branch() {
+        let mut file = ConfigFile::parse(CONFIG).unwrap();
+        let master = "master".try_into().unwrap();
+
+        file.add_branch(&"owner/repo".parse().unwrap(), None)
+            .unwrap();
+        file.remove_branch(&"owner/repo/master".parse().unwrap(), None)
+            .expect_err("the default branch is not known");
+        file.remove_branch(&"owner/repo/master".parse().unwrap(), Some(&master))
+            .unwrap();
+
+        assert!(file.to_string().contains("branches = []"));
+    }
+
+    #[test]
+    fn enable_entries() {
+        let mut file = ConfigFile::parse(CONFIG).unwrap();
+
+        file.set_pull_request_enabled(None, 12309.try_into().unwrap(), false)
+            .unwrap();
+        file.set_pull_request_enabled(None, 11285.try_into().unwrap(), true)
+            .unwrap();
+        file.set_patch_enabled(None, &"remove-tab".try_into().unwrap(), false)
+            .unwrap_err();
+
+        pretty_assertions::assert_eq!(
+            file.to_string(),
+            r#"repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+
+pull-requests = [
+  # syntax highlighting for nginx files
+  { number = 12309, enabled = false },
+  # adds file explorer
+  { number = 11285, note = "file explorer" },
+]
+
+# no branches yet
+branches = []
+"#
+        );
+
+        file.set_pull_request_enabled(None, 12309.try_into().unwrap(), true)
+            .unwrap();
+
+        assert!(file.to_string().contains("\n  \"12309\",\n"));
+    }
+
+    #[test]
+    fn enable_entries_of_profile() {
+        let mut file = ConfigFile::parse(&format!(
+            r#"{CONFIG}
+[profiles.stable]
+pull-requests = ["11285 @ a1b2c3"]
+"#
+        ))
+        .unwrap();
+
+        // 11285 is overridden by the profile, but 12309 is only in the shared config
+        file.set_pull_request_enabled(Some("stable"), 11285.try_into().unwrap(), false)
+            .unwrap();
+        file.set_pull_request_enabled(Some("stable"), 12309.try_into().unwrap(), false)
+            .unwrap();
+        file.set_patch_enabled(Some("stable"), &"remove-tab".try_into().unwrap(), false)
+            .unwrap_err();
+
+        pretty_assertions::assert_eq!(
+            file.to_string(),
+            r#"repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+
+pull-requests = [
+  # syntax highlighting for nginx files
+  { number = 12309, enabled = false },
+  # adds file explorer
+  { number = 11285, note = "file explorer" },
+]
+
+# no branches yet
+branches = []
+
+[profiles.stable]
+pull-requests = [{ number = 11285, commit = "a1b2c3", enabled = false }]
+"#
+        );
+    }
+
+    #[test]
+    fn remove_entries() {
+        let mut file = ConfigFile::parse(CONFIG).unwrap();
+
+        file.remove_pull_request(11285.try_into().unwrap()).unwrap();
+        file.remove_pull_request(1.try_into().unwrap()).unwrap_err();
+        file.remove_patch(&"remove-tab".try_into().unwrap())
+            .unwrap_err();
+
+        pretty_assertions::assert_eq!(
+            file.to_string(),
+            r#"repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+
+pull-requests = [
+  # syntax highlighting for nginx files
+  "12309",
+]
+
+# no branches yet
+branches = []
+"#
+        );
+    }
+}
@@ -0,0 +1,538 @@
+//! Combine patchy's config file with its includes and the user's global config
+//!
+//! Layers are merged from lowest to highest precedence:
+//!
+//! 1. The user's global config, `$XDG_CONFIG_HOME/patchy/config.toml`.
+//!    It can only set the keys in [`USER_KEYS`], and can't `include` files
+//! 2. Files listed in `include` of the repository's config, in order
+//! 3. The repository's config, `.patchy/config.toml`
+//!
+//! Any file can `include` other files, paths are relative to the including file.
+//! An included file has lower precedence than the file that includes it.
+//! A file which is included several times is only loaded the first time.
+//!
+//! When merging a layer into the ones below it:
+//!
+//! - Tables are merged key by key
+//! - Arrays are concatenated, entries of lower layers come first. A pull request, branch or
+//!   patch which is already in a lower layer is replaced by the entry of the higher layer
+//! - Any other value replaces the value of the lower layers
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow, bail};
+use toml::{Table, Value};
+
+use super::{BranchEntry, FILE, FILE_PATH, Patch, PullRequest};
+
+/// Key which lists the files to include
+const INCLUDE: &str = "include";
+
+/// Key whose value is hidden when the config is printed
+const SECRET: &str = "github-token";
+
+/// Keys which the user's global config can set. They are not specific to a repository,
+/// unlike e.g. `repo` or `pull-requests`
+const USER_KEYS: [&str; 5] = [
+    "merge-strategy",
+    "use-gh-cli",
+    "github-host",
+    "github-token",
+    "push-remote",
+];
+
+/// Location of the user's global config, if it can be determined
+pub fn user_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("patchy").join(FILE))
+}
+
+/// Where a value of the resolved config comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    /// The value was set by this file
+    Value(PathBuf),
+    /// Each element of the array comes from the respective file
+    Array(Vec<PathBuf>),
+    /// Origins of the keys of a table
+    Table(BTreeMap<String, Origin>),
+}
+
+impl Origin {
+    /// All of the `value` comes from `path`
+    fn new(value: &Value, path: &Path) -> Self {
+        match value {
+            Value::Table(table) => Self::Table(
+                table
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Self::new(value, path)))
+                    .collect(),
+            ),
+            Value::Array(array) => Self::Array(vec![path.to_path_buf(); array.len()]),
+            Value::String(_)
+            | Value::Integer(_)
+            | Value::Float(_)
+            | Value::Boolean(_)
+            | Value::Datetime(_) => Self::Value(path.to_path_buf()),
+        }
+    }
+}
+
+/// The config after all of its layers are merged
+#[derive(Debug, Default)]
+pub struct Resolved {
+    /// The merged config
+    pub table: Table,
+    /// Where each key of `table` comes from
+    origins: BTreeMap<String, Origin>,
+    /// Every file that was merged, from lowest to highest precedence
+    pub files: Vec<PathBuf>,
+}
+
+impl Resolved {
+    /// Resolve the repository's config file, which has the given contents
+    pub fn new(contents: &str) -> Result<Self> {
+        let mut layers = user_layer()?.into_iter().collect::<Vec<_>>();
+
+        let table = contents
+            .parse::<Table>()
+            .map_err(|err| anyhow!("failed to parse {}:\n{err}", FILE_PATH.display()))?;
+        load(&FILE_PATH, table, &mut Vec::new(), &mut layers)?;
+
+        Ok(Self::from_layers(layers))
+    }
+
+    /// Resolve only the user's global config, e.g. outside of a patchy repository
+    pub fn user() -> Result<Self> {
+        Ok(Self::from_layers(user_layer()?.into_iter().collect()))
+    }
+
+    /// Merge the `layers`, which are ordered from lowest to highest precedence
+    fn from_layers(layers: Vec<(PathBuf, Table)>) -> Self {
+        let mut resolved = Self::default();
+
+        for (path, table) in layers {
+            merge(&mut resolved.table, &mut resolved.origins, table, &path);
+            resolved.files.push(path);
+        }
+
+        resolved
+    }
+}
+
+/// The user's global config, if it exists
+///
+/// It only holds the settings in [`USER_KEYS`], which apply to every repository
+fn user_layer() -> Result<Option<(PathBuf, Table)>> {
+    let Some(path) = user_config_path().filter(|path| path.exists()) else {
+        return Ok(None);
+    };
+    let table = read(&path)?;
+    check_user_keys(&table, &path)?;
+
+    Ok(Some((path, table)))
+}
+
+/// Fail if the user's global config at `path` sets a key which is not in [`USER_KEYS`]
+fn check_user_keys(table: &Table, path: &Path) -> Result<()> {
+    if let Some(key) = table.keys().find(|key| !USER_KEYS.contains(&key.as_str())) {
+        bail!(
+            "`{key}` can not be set in {}, it would apply to every repository. \
+             Move it to {} instead. The global config can only set: {}",
+            path.display(),
+            FILE_PATH.display(),
+            USER_KEYS.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Read and parse the TOML file at `path`
+fn read(path: &Path) -> Result<Table> {
+    fs::read_to_string(path)
+        .map_err(|err| anyhow!("failed to read {}: {err}", path.display()))?
+        .parse::<Table>()
+        .map_err(|err| anyhow!("failed to parse {}:\n{err}", path.display()))
+}
+
+/// Push the layers of the file at `path` with the given contents to `layers`
+///
+/// The included files are pushed before the file itself, unless they are already in `layers`.
+/// `stack` contains the files which are currently being included, to detect cycles
+fn load(
+    path: &Path,
+    mut table: Table,
+    stack: &mut Vec<PathBuf>,
+    layers: &mut Vec<(PathBuf, Table)>,
+) -> Result<()> {
+    if stack.iter().any(|included| included == path) {
+        bail!("{} includes itself", path.display());
+    }
+
+    let includes = match table.remove(INCLUDE) {
+        None => Vec::new(),
+        Some(Value::Array(includes)) => includes
+            .into_iter()
+            .map(|include| {
+                include.as_str().map(str::to_owned).ok_or_else(|| {
+                    anyhow!(
+                        "`{INCLUDE}` in {} must only contain paths, found: {include}",
+                        path.display()
+                    )
+                })
+            })
+            .collect::<Result<_>>()?,
+        Some(other) => bail!(
+            "`{INCLUDE}` in {} must be an array of paths, found: {other}",
+            path.display()
+        ),
+    };
+
+    stack.push(path.to_path_buf());
+
+    for include in includes {
+        let include = path
+            .parent()
+            .map_or_else(|| PathBuf::from(&include), |dir| dir.join(&include));
+        // e.g. two files which both include a shared file
+        if layers.iter().any(|(loaded, _)| *loaded == include) {
+            continue;
+        }
+        load(&include, read(&include)?, stack, layers)?;
+    }
+
+    stack.pop();
+    layers.push((path.to_path_buf(), table));
+
+    Ok(())
+}
+
+/// Merge the `layer` from the file at `path` into `table`
+fn merge(table: &mut Table, origins: &mut BTreeMap<String, Origin>, layer: Table, path: &Path) {
+    for (key, value) in layer {
+        match (table.get_mut(&key), origins.get_mut(&key), value) {
+            (Some(Value::Table(table)), Some(Origin::Table(origins)), Value::Table(layer)) => {
+                merge(table, origins, layer, path);
+            }
+            (Some(Value::Array(array)), Some(Origin::Array(origins)), Value::Array(layer)) => {
+                for value in layer {
+                    let duplicate = entry_key(&key, &value).and_then(|entry| {
+                        array
+                            .iter()
+                            .position(|other| entry_key(&key, other).as_ref() == Some(&entry))
+                    });
+
+                    if let Some((other, origin)) =
+                        duplicate.and_then(|index| array.get_mut(index).zip(origins.get_mut(index)))
+                    {
+                        *other = value;
+                        *origin = path.to_path_buf();
+                    } else {
+                        array.push(value);
+                        origins.push(path.to_path_buf());
+                    }
+                }
+            }
+            (_, _, value) => {
+                origins.insert(key.clone(), Origin::new(&value, path));
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// What identifies the `value` in the array at `key`, if it is an entry, e.g. the number
+/// of a pull request. Entries which fail to parse are reported when the config is read
+fn entry_key(key: &str, value: &Value) -> Option<String> {
+    match key {
+        "pull-requests" => value
+            .clone()
+            .try_into::<PullRequest>()
+            .ok()
+            .map(|pull_request| pull_request.number.to_string()),
+        "branches" => value
+            .clone()
+            .try_into::<BranchEntry>()
+            .ok()
+            .map(|branch| branch.remote.name()),
+        "patches" => value
+            .clone()
+            .try_into::<Patch>()
+            .ok()
+            .map(|patch| patch.name.to_string()),
+        _ => None,
+    }
+}
+
+impl Display for Resolved {
+    /// Print the config as TOML, with the file each value comes from as a comment
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# resolved from, in order of increasing precedence:")?;
+        for file in &self.files {
+            writeln!(f, "#   {}", display_path(file))?;
+        }
+
+        write_table(f, &[], &self.table, &self.origins)
+    }
+}
+
+/// Write the `table` at the key `path`, with the origin of each value as a comment
+fn write_table(
+    f: &mut fmt::Formatter<'_>,
+    path: &[&str],
+    table: &Table,
+    origins: &BTreeMap<String, Origin>,
+) -> fmt::Result {
+    let (tables, values): (Vec<_>, Vec<_>) = table.iter().partition(|(_, value)| value.is_table());
+
+    if !values.is_empty() {
+        writeln!(f)?;
+    }
+
+    if !path.is_empty() && !values.is_empty() {
+        writeln!(f, "[{}]", path.join("."))?;
+    }
+
+    for (key, value) in values {
+        match (value, origins.get(key)) {
+            (Value::Array(array), Some(Origin::Array(origins))) => {
+                writeln!(f, "{key} = [")?;
+                for (element, origin) in array.iter().zip(origins) {
+                    writeln!(f, "  {element}, # {}", display_path(origin))?;
+                }
+                writeln!(f, "]")?;
+            }
+            (_, Some(Origin::Value(origin))) if key == SECRET => {
+                writeln!(f, "{key} = \"***\" # {}", display_path(origin))?;
+            }
+            (value, Some(Origin::Value(origin))) => {
+                writeln!(f, "{key} = {value} # {}", display_path(origin))?;
+            }
+            (value, _) => writeln!(f, "{key} = {value}")?,
+        }
+    }
+
+    for (key, value) in tables {
+        let Value::Table(table) = value else {
+            continue;
+        };
+        let empty = BTreeMap::new();
+        let origins = match origins.get(key) {
+            Some(Origin::Table(origins)) => origins,
+            _ => &empty,
+        };
+        let mut path = path.to_vec();
+        path.push(key);
+        write_table(f, &path, table, origins)?;
+    }
+
+    Ok(())
+}
+
+/// Display the `path` relative to the repository, if it is inside of it
+fn display_path(path: &Path) -> String {
+    path.strip_prefix(&*crate::git::ROOT)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_layers() {
+        let layer = |path: &str, contents: &str| (PathBuf::from(path), contents.parse().unwrap());
+
+        let resolved = Resolved::from_layers(vec![
+            layer(
+                "user.toml",
+                r#"
+merge-strategy = "rebase"
+use-gh-cli = true
+github-token = "ghp_123"
+"#,
+            ),
+            layer(
+                "base.toml",
+                r#"
+pull-requests = ["1"]
+
+[profiles.stable]
+local-branch = "stable"
+"#,
+            ),
+            layer(
+                "team.toml",
+                r#"
+repo = "helix-editor/helix"
+pull-requests = ["2", "3"]
+
+[profiles.stable]
+remove-pull-requests = [3]
+"#,
+            ),
+            layer(
+                "config.toml",
+                r#"
+merge-strategy = "squash"
+local-branch = "patchy"
+pull-requests = ["4", "2 @ a1b2c3"]
+"#,
+            ),
+        ]);
+
+        pretty_assertions::assert_eq!(
+            resolved.table,
+            r#"
+merge-strategy = "squash"
+use-gh-cli = true
+github-token = "ghp_123"
+repo = "helix-editor/helix"
+local-branch = "patchy"
+pull-requests = ["1", "2 @ a1b2c3", "3", "4"]
+
+[profiles.stable]
+local-branch = "stable"
+remove-pull-requests = [3]
+"#
+            .parse::<Table>()
+            .unwrap()
+        );
+
+        pretty_assertions::assert_eq!(
+            resolved.to_string(),
+            r#"# resolved from, in order of increasing precedence:
+#   user.toml
+#   base.toml
+#   team.toml
+#   config.toml
+
+github-token = "***" # user.toml
+local-branch = "patchy" # config.toml
+merge-strategy = "squash" # config.toml
+pull-requests = [
+  "1", # base.toml
+  "2 @ a1b2c3", # config.toml
+  "3", # team.toml
+  "4", # config.toml
+]
+repo = "helix-editor/helix" # team.toml
+use-gh-cli = true # user.toml
+
+[profiles.stable]
+local-branch = "stable" # base.toml
+remove-pull-requests = [
+  3, # team.toml
+]
+"#
+        );
+    }
+
+    #[test]
+    fn user_keys() {
+        let path = Path::new("user.toml");
+        let check = |contents: &str| check_user_keys(&contents.parse().unwrap(), path);
+
+        check("merge-strategy = 'squash'\ngithub-token = 'ghp_123'\npush-remote = 'origin'")
+            .unwrap();
+
+        for contents in [
+            "pull-requests = ['1']",
+            "branches = ['owner/repo/branch']",
+            "patches = ['fix']",
+            "include = ['team.toml']",
+            "[profiles.stable]\nlocal-branch = 'stable'",
+        ] {
+            check(contents).expect_err(contents);
+        }
+    }
+
+    #[test]
+    fn include_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        fs::write(
+            dir.path().join("team.toml"),
+            "include = ['base.toml']\nrepo = 'a/b'",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("base.toml"),
+            "repo = 'c/d'\nlocal-branch = 'x'",
+        )
+        .unwrap();
+
+        let mut layers = Vec::new();
+        load(
+            &config,
+            "include = ['team.toml']\nlocal-branch = 'y'"
+                .parse()
+                .unwrap(),
+            &mut Vec::new(),
+            &mut layers,
+        )
+        .unwrap();
+        let resolved = Resolved::from_layers(layers);
+
+        assert_eq!(
+            resolved.files,
+            [
+                dir.path().join("base.toml"),
+                dir.path().join("team.toml"),
+                config.clone()
+            ],
+            "included files come before the file that includes them"
+        );
+        assert_eq!(
+            resolved.table.get("repo").and_then(Value::as_str),
+            Some("a/b")
+        );
+        assert_eq!(
+            resolved.table.get("local-branch").and_then(Value::as_str),
+            Some("y")
+        );
+
+        // both `a.toml` and `team.toml` include `base.toml`
+        fs::write(dir.path().join("a.toml"), "include = ['base.toml']").unwrap();
+        let mut layers = Vec::new();
+        load(
+            &config,
+            "include = ['a.toml', 'team.toml']".parse().unwrap(),
+            &mut Vec::new(),
+            &mut layers,
+        )
+        .unwrap();
+        assert_eq!(
+            Resolved::from_layers(layers).files,
+            [
+                dir.path().join("base.toml"),
+                dir.path().join("a.toml"),
+                dir.path().join("team.toml"),
+                config
+            ],
+            "a file which is included twice is loaded once"
+        );
+
+        fs::write(dir.path().join("base.toml"), "include = ['team.toml']").unwrap();
+        load(
+            &dir.path().join("team.toml"),
+            "include = ['base.toml']".parse().unwrap(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+    }
+}
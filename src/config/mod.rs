@@ -0,0 +1,2016 @@
+//! Patchy's config
+
+use anyhow::{anyhow, bail};
+use colored::Colorize as _;
+use itertools::Itertools;
+use nutype::nutype;
+use std::{
+    convert::Infallible,
+    env,
+    fmt::Display,
+    marker::PhantomData,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::LazyLock,
+};
+use tap::Pipe as _;
+
+use indexmap::{IndexMap, IndexSet};
+use serde::{
+    Deserialize,
+    de::{self, DeserializeOwned},
+};
+
+/// Relative path to root of patchy's configuration
+pub static ROOT: LazyLock<String> =
+    LazyLock::new(|| env::var("PATCHY_CONFIG_ROOT").unwrap_or_else(|_| ".patchy".into()));
+
+/// Absolute path to root of patchy's configuration
+pub static PATH: LazyLock<PathBuf> = LazyLock::new(|| crate::git::ROOT.join(&*ROOT));
+
+/// Absolute path to patchy's config file
+pub static FILE_PATH: LazyLock<PathBuf> = LazyLock::new(|| PATH.join(FILE));
+
+/// Patchy's config file name
+pub const FILE: &str = "config.toml";
+
+pub mod edit;
+pub mod layer;
+
+/// Represents the TOML config
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// Local branch where patchy will do all of its work
+    pub local_branch: BranchName,
+    /// List of patches to apply
+    #[serde(default)]
+    pub patches: IndexSet<Patch>,
+    /// List of pull request to apply
+    #[serde(default)]
+    pub pull_requests: Vec<PullRequest>,
+    /// List of branches to apply
+    #[serde(default)]
+    pub branches: Vec<BranchEntry>,
+    /// Branch of the remote repository. If omitted, uses the default branch of `repo`
+    pub remote_branch: Option<Branch>,
+    /// Remote repository where all of the `branches` and `pull_requests` are
+    pub repo: String,
+    /// Existing git remote to fetch `remote-branch` from, e.g. `upstream`
+    ///
+    /// If omitted, uses a remote which points at `repo`, if there is one
+    pub base_remote: Option<String>,
+    /// Named variations of this config, selected with `patchy run --profile`
+    #[serde(default)]
+    pub profiles: IndexMap<String, Profile>,
+    /// How to merge entries which do not specify a `merge-strategy`
+    #[serde(default)]
+    pub merge_strategy: MergeStrategy,
+    /// Use the `gh` CLI to interact with the GitHub API
+    #[serde(default)]
+    pub use_gh_cli: bool,
+    /// Host of GitHub, e.g. `github.example.com` for GitHub Enterprise. If omitted, uses `github.com`
+    pub github_host: Option<String>,
+    /// Token to authenticate with the GitHub API, e.g. to raise its rate limit.
+    /// It belongs in the user's global config, so that it is not committed
+    pub github_token: Option<String>,
+    /// Git remote to push `local-branch` to, after `patchy run` overwrites it
+    pub push_remote: Option<String>,
+    /// GitHub search queries, e.g. `label:ready-for-review is:open`, which expand
+    /// into the pull requests that they match
+    #[serde(default)]
+    pub pull_request_queries: Vec<String>,
+    /// Messages of the commits that patchy creates for pull requests and branches
+    #[serde(default)]
+    pub commit_message: CommitMessage,
+    /// Identity and signing of the commits that patchy creates
+    #[serde(default)]
+    pub committer: Committer,
+    /// Commands which check patchy's branch while it is built
+    #[serde(default)]
+    pub hooks: Hooks,
+}
+
+/// Commands which check patchy's branch while it is built, e.g. `cargo check`
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    /// Run after the base branch is checked out
+    #[serde(default)]
+    pub after_base: Vec<String>,
+    /// Run after each pull request, branch and patch is merged
+    #[serde(default)]
+    pub after_entry: Vec<String>,
+    /// Run after everything is merged
+    #[serde(default)]
+    pub after_all: Vec<String>,
+    /// What to do when a hook fails
+    #[serde(default)]
+    pub policy: HookPolicy,
+}
+
+/// What to do when a hook fails
+#[derive(Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookPolicy {
+    /// Stop, without changing `local-branch`
+    #[default]
+    Fail,
+    /// Report the failure, and carry on
+    Warn,
+    /// Remove the entry that was just merged, and carry on.
+    /// For `after-base` and `after-all` this is the same as `warn`
+    Drop,
+}
+
+/// Identity and signing of the commits that patchy creates
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Committer {
+    /// Name of the committer, e.g. `fork-bot`. If omitted, uses `user.name` of git
+    pub name: Option<String>,
+    /// Email of the committer. If omitted, uses `user.email` of git
+    pub email: Option<String>,
+    /// Add a `Signed-off-by` trailer to each commit, or to none of them.
+    /// If omitted, only patches are signed off, like they always have been
+    pub sign_off: Option<bool>,
+    /// Sign each commit with this format. If omitted, uses `commit.gpgSign` of git
+    pub sign: Option<SignatureFormat>,
+    /// Key to sign the commits with, e.g. a GPG key ID or the path to an SSH key.
+    /// If omitted, uses `user.signingKey` of git
+    pub signing_key: Option<String>,
+}
+
+/// How commits are signed
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignatureFormat {
+    /// Sign with GPG
+    Gpg,
+    /// Sign with an SSH key
+    Ssh,
+}
+
+impl SignatureFormat {
+    /// Value of `gpg.format` of git for this format
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Gpg => "openpgp",
+            Self::Ssh => "ssh",
+        }
+    }
+}
+
+/// Messages of the commits that patchy creates for pull requests and branches
+///
+/// The message and the trailers of pull requests can contain these placeholders:
+///
+/// - `{number}`: number of the pull request, e.g. `12254`
+/// - `{title}`: title of the pull request
+/// - `{url}`: link to the pull request
+/// - `{author}`: GitHub login of the author of the pull request
+/// - `{author-email}`: the author's `noreply` email on GitHub
+/// - `{head}`: commit of the pull request which was merged
+/// - `{base}`: commit of the branch the pull request targets
+///
+/// The message and the trailers of branches can contain these placeholders:
+///
+/// - `{branch}`: the branch, e.g. `helix-editor/helix/master`
+/// - `{head}`: commit of the branch which was merged
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CommitMessage {
+    /// Message of the commit of a merged pull request
+    #[serde(default = "CommitMessage::default_pull_request")]
+    pub pull_request: String,
+    /// Trailers appended to the message of a pull request, e.g. `Patchy-PR: {number}`
+    #[serde(default = "CommitMessage::default_trailers")]
+    pub trailers: Vec<String>,
+    /// Message of the commit of a merged branch
+    #[serde(default = "CommitMessage::default_branch")]
+    pub branch: String,
+    /// Trailers appended to the message of a branch, e.g. `Patchy-Branch: {branch}`
+    #[serde(default = "CommitMessage::default_branch_trailers")]
+    pub branch_trailers: Vec<String>,
+}
+
+impl Default for CommitMessage {
+    fn default() -> Self {
+        Self {
+            pull_request: Self::default_pull_request(),
+            trailers: Self::default_trailers(),
+            branch: Self::default_branch(),
+            branch_trailers: Self::default_branch_trailers(),
+        }
+    }
+}
+
+impl CommitMessage {
+    /// Default message of the commit of a merged pull request
+    fn default_pull_request() -> String {
+        "patchy: Merge pull request #{number} {title}".to_string()
+    }
+
+    /// Trailers which map each commit back to the pull request it comes from
+    fn default_trailers() -> Vec<String> {
+        vec![
+            "Patchy-PR: {number}".to_string(),
+            "Patchy-Head: {head}".to_string(),
+        ]
+    }
+
+    /// Default message of the commit of a merged branch
+    fn default_branch() -> String {
+        "patchy: Merge branch {branch}".to_string()
+    }
+
+    /// Trailers which map each commit back to the branch it comes from
+    fn default_branch_trailers() -> Vec<String> {
+        vec![
+            "Patchy-Branch: {branch}".to_string(),
+            "Patchy-Head: {head}".to_string(),
+        ]
+    }
+
+    /// Message of the commit of a pull request, with the placeholders replaced by the `values`
+    pub fn pull_request(&self, values: &[(&str, &str)]) -> Message {
+        Message::new(&self.pull_request, &self.trailers, values)
+    }
+
+    /// Message of the commit of a branch, with the placeholders replaced by the `values`
+    pub fn branch(&self, values: &[(&str, &str)]) -> Message {
+        Message::new(&self.branch, &self.branch_trailers, values)
+    }
+}
+
+/// Message of a commit that patchy creates, and the trailers appended to it
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Message {
+    /// The message, without the trailers
+    pub text: String,
+    /// Trailers appended to the message, e.g. `Patchy-PR: 12254`
+    pub trailers: Vec<String>,
+}
+
+impl Message {
+    /// The `text` and the `trailers`, with the placeholders replaced by the `values`
+    fn new(text: &str, trailers: &[String], values: &[(&str, &str)]) -> Self {
+        Self {
+            text: expand(text, values),
+            trailers: trailers
+                .iter()
+                .map(|trailer| expand(trailer, values))
+                .collect(),
+        }
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            trailers: Vec::new(),
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)?;
+
+        if !self.trailers.is_empty() {
+            write!(f, "\n\n{}", self.trailers.join("\n"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replace each `{placeholder}` of the `template` by its value in `values`
+///
+/// This is a single pass over the `template`, so placeholders inside of the values,
+/// e.g. a pull request titled `{author-email}`, are kept as they are
+fn expand(template: &str, values: &[(&str, &str)]) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some((before, after)) = rest.split_once('{') {
+        expanded.push_str(before);
+
+        let replacement = after.split_once('}').and_then(|(placeholder, after)| {
+            values
+                .iter()
+                .find(|(name, _)| *name == placeholder)
+                .map(|(_, value)| (*value, after))
+        });
+
+        if let Some((value, after)) = replacement {
+            expanded.push_str(value);
+            rest = after;
+        } else {
+            expanded.push('{');
+            rest = after;
+        }
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+/// A named variation of the config
+///
+/// Entries are first removed from the shared config, and then added
+#[derive(Deserialize, Debug, Eq, PartialEq, Default, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    /// Overrides the shared `local-branch`
+    pub local_branch: Option<BranchName>,
+    /// Pull requests to add
+    #[serde(default)]
+    pub pull_requests: Vec<PullRequest>,
+    /// Branches to add
+    #[serde(default)]
+    pub branches: Vec<BranchEntry>,
+    /// Patches to add
+    #[serde(default)]
+    pub patches: IndexSet<Patch>,
+    /// Pull requests of the shared config to leave out
+    #[serde(default)]
+    pub remove_pull_requests: Vec<PrNumber>,
+    /// Branches of the shared config to leave out, e.g. `helix-editor/helix/master`
+    #[serde(default)]
+    pub remove_branches: Vec<BranchRemote>,
+    /// Patches of the shared config to leave out
+    #[serde(default)]
+    pub remove_patches: Vec<PatchName>,
+}
+
+impl Config {
+    /// Apply the profile called `name` to this config
+    pub fn with_profile(mut self, name: &str) -> anyhow::Result<Self> {
+        let Some(profile) = self.profiles.shift_remove(name) else {
+            bail!(
+                "profile `{name}` does not exist. Available profiles: {}",
+                self.profiles.keys().join(", ")
+            );
+        };
+
+        self.pull_requests
+            .retain(|pr| !profile.remove_pull_requests.contains(&pr.number));
+        self.branches.retain(|branch| {
+            !profile
+                .remove_branches
+                .iter()
+                .any(|remote| remote.is_same_branch(&branch.remote, None))
+        });
+        self.patches
+            .retain(|patch| !profile.remove_patches.contains(&patch.name));
+
+        // an entry of the profile which is already in the shared config replaces it
+        for pull_request in profile.pull_requests {
+            match self
+                .pull_requests
+                .iter_mut()
+                .find(|existing| existing.number == pull_request.number)
+            {
+                Some(existing) => *existing = pull_request,
+                None => self.pull_requests.push(pull_request),
+            }
+        }
+        for branch in profile.branches {
+            match self
+                .branches
+                .iter_mut()
+                .find(|existing| existing.remote.is_same_branch(&branch.remote, None))
+            {
+                Some(existing) => *existing = branch,
+                None => self.branches.push(branch),
+            }
+        }
+        for patch in profile.patches {
+            match self
+                .patches
+                .iter()
+                .position(|existing| existing.name == patch.name)
+            {
+                Some(index) => {
+                    self.patches.shift_remove_index(index);
+                    self.patches.shift_insert(index, patch);
+                }
+                None => {
+                    self.patches.insert(patch);
+                }
+            }
+        }
+
+        if let Some(local_branch) = profile.local_branch {
+            self.local_branch = local_branch;
+        }
+
+        Ok(self)
+    }
+
+    /// Read and parse patchy's config file, merged with its includes
+    /// and the user's global config
+    pub fn read() -> anyhow::Result<Self> {
+        Self::resolve(&read_file()?)
+    }
+
+    /// [`Config::read`], with the `profile` applied if one is given
+    pub fn read_with_profile(profile: Option<&str>) -> anyhow::Result<Self> {
+        let config = Self::read()?;
+
+        let Some(profile) = profile else {
+            return Ok(config);
+        };
+
+        log::info!("Using profile {}", profile.bright_cyan());
+        config.with_profile(profile)
+    }
+
+    /// Parse the config file with the given `contents`, merged with its includes
+    /// and the user's global config
+    pub fn resolve(contents: &str) -> anyhow::Result<Self> {
+        let resolved = layer::Resolved::new(contents)?;
+
+        for file in &resolved.files {
+            log::debug!("Using configuration file {}", file.display());
+        }
+
+        Self::deserialize(resolved.table).map_err(|err| {
+            anyhow!(
+                "Could not parse `{}/{FILE}` configuration file:\n{err}",
+                ROOT.as_str()
+            )
+        })
+    }
+}
+
+/// Settings of the config which apply to every command
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct GitHubSettings {
+    /// See [`Config::github_host`]
+    pub github_host: Option<String>,
+    /// See [`Config::github_token`]
+    pub github_token: Option<String>,
+}
+
+/// `github-host` and `github-token` of the config, for the GitHub API
+///
+/// Outside of a patchy repository, only the user's global config is used
+pub fn github_settings() -> GitHubSettings {
+    let settings = crate::git::find_root()
+        .and_then(|_| read_file())
+        .and_then(|contents| layer::Resolved::new(&contents))
+        .or_else(|_| layer::Resolved::user())
+        .and_then(|resolved| Ok(GitHubSettings::deserialize(resolved.table)?));
+
+    settings.unwrap_or_else(|err| {
+        // the command reports the error when it reads the config
+        log::debug!("failed to read the settings of GitHub: {err}");
+        GitHubSettings::default()
+    })
+}
+
+/// Read the contents of patchy's config file
+pub fn read_file() -> anyhow::Result<String> {
+    std::fs::read_to_string(&*FILE_PATH).map_err(|err| {
+        anyhow!(
+            "Could not read configuration file at {}/{FILE}: {err}",
+            ROOT.as_str()
+        )
+    })
+}
+
+/// A repository on GitHub, e.g. `helix-editor/helix`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Repository {
+    /// e.g. `helix-editor`
+    pub owner: RepoOwner,
+    /// e.g. `helix`
+    pub repo: RepoName,
+}
+
+impl Display for Repository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.repo)
+    }
+}
+
+impl FromStr for Repository {
+    type Err = anyhow::Error;
+
+    /// Parse a repository of the form `owner/repo`, without a branch or a commit
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((owner, repo)) = s
+            .split_once('/')
+            .filter(|(_, repo)| !repo.contains(['/', '@']) && !repo.contains(char::is_whitespace))
+        else {
+            bail!("Invalid repository: {s}. Expected format: owner/repo");
+        };
+
+        Ok(Self {
+            owner: RepoOwner::try_new(owner)?,
+            repo: RepoName::try_new(repo)?,
+        })
+    }
+}
+
+/// Represents e.g. `helix-editor/helix/master @ 1a2b3c`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Remote {
+    /// e.g. `helix-editor`
+    pub owner: RepoOwner,
+    /// e.g. `helix`
+    pub repo: RepoName,
+    /// e.g. `master`. If omitted, uses the default branch of the repository
+    pub branch: Option<BranchName>,
+    /// e.g. `1a2b3c`
+    pub commit: Option<CommitId>,
+}
+
+impl Remote {
+    /// `true` if both remotes refer to the same branch, regardless of the commit
+    ///
+    /// A remote without a branch refers to the `default_branch` of its repository.
+    /// If it is not known, `owner/repo` and `owner/repo/master` are different branches
+    pub fn is_same_branch(&self, other: &Self, default_branch: Option<&BranchName>) -> bool {
+        let branch = |remote: &Self| remote.branch.clone().or_else(|| default_branch.cloned());
+        self.owner == other.owner && self.repo == other.repo && branch(self) == branch(other)
+    }
+}
+
+impl Display for Remote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.repo)?;
+        if let Some(branch) = &self.branch {
+            write!(f, "/{branch}")?;
+        }
+        if let Some(commit) = &self.commit {
+            write!(f, " @ {commit}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Remote {
+    type Err = anyhow::Error;
+
+    /// Parse remotes of the form:
+    ///
+    /// ```text
+    /// helix-editor/helix/master @ 1a2b3c
+    /// ^^^^^^^^^^^ owner  ^^^^^^ branch
+    ///              ^^^^^ repo     ^^^^^^ commit
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Ok(Ref { item, commit }) = s.parse::<Ref>();
+
+        let mut parts = item.split('/');
+        let Some([owner, repo]) = parts.next_array() else {
+            bail!("Invalid branch format: {item}. Expected format: owner/repo/branch");
+        };
+
+        let owner = RepoOwner::try_new(owner)?;
+        let repo = RepoName::try_new(repo)?;
+
+        let branch = parts
+            // insert back the removed '/', this could be part of the branch itself
+            // e.g. in `helix-editor/helix/master/main` the branch is considered to be `master/main`
+            //
+            // NOTE: Using fully qualified syntax, as Rust will add `Iterator::intersperse`
+            // in a future version.
+            .pipe(|it| Itertools::intersperse(it, "/"))
+            .collect::<String>()
+            .pipe(|s| {
+                // if branch name is ommitted (e.g. `helix-editor/helix`)
+                // then the default branch of the repository is used
+                (!s.is_empty()).then_some(s)
+            })
+            .map(BranchName::try_new)
+            .transpose()
+            .map_err(|err| anyhow!("invalid branch name: {err}"))?;
+
+        Ok(Self {
+            owner,
+            repo,
+            branch,
+            commit,
+        })
+    }
+}
+
+/// A branch of any git repository, e.g. `https://git.sr.ht/~user/helix#feature @ 1a2b3c`
+///
+/// The branch is fetched straight from the repository, without going through GitHub's API
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct GitUrl {
+    /// Anything `git fetch` accepts: `https://`, `ssh://`, `git@host:path`, `file://` or a local path
+    pub url: String,
+    /// e.g. `feature`. If omitted, uses the default branch of the repository
+    pub branch: Option<BranchName>,
+    /// e.g. `1a2b3c`
+    pub commit: Option<CommitId>,
+}
+
+impl GitUrl {
+    /// `true` if `s` is a git URL or a path, rather than `owner/repo/branch`
+    ///
+    /// Local paths must be absolute or start with `.` or `~`,
+    /// since `owner/repo/branch` is also a valid relative path
+    pub fn is_url(s: &str) -> bool {
+        // branch names can't contain ':', so this can't be a GitHub remote
+        s.contains(':') || s.starts_with(['/', '.', '~'])
+    }
+
+    /// The branch that is fetched from the repository, `HEAD` if there is no branch
+    pub fn upstream_branch(&self) -> BranchName {
+        self.branch
+            .clone()
+            .unwrap_or_else(|| BranchName::try_new("HEAD").expect("`HEAD` is a valid branch name"))
+    }
+
+    /// Name of the repository, e.g. `helix` for `git@example.com:user/helix.git`
+    pub fn repo_name(&self) -> &str {
+        self.url
+            .trim_end_matches('/')
+            .rsplit(['/', ':', '\\'])
+            .next()
+            .map(|name| name.trim_end_matches(".git"))
+            .filter(|name| !name.is_empty())
+            .unwrap_or("repo")
+    }
+
+    /// `true` if both refer to the same branch, regardless of the commit
+    ///
+    /// A URL without a branch refers to the `default_branch` of its repository
+    pub fn is_same_branch(&self, other: &Self, default_branch: Option<&BranchName>) -> bool {
+        let branch = |url: &Self| url.branch.clone().or_else(|| default_branch.cloned());
+        self.url == other.url && branch(self) == branch(other)
+    }
+}
+
+impl Display for GitUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.url)?;
+        if let Some(branch) = &self.branch {
+            write!(f, "#{branch}")?;
+        }
+        if let Some(commit) = &self.commit {
+            write!(f, " @ {commit}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GitUrl {
+    type Err = anyhow::Error;
+
+    /// Parse git URLs of the form:
+    ///
+    /// ```text
+    /// https://git.sr.ht/~user/helix#feature @ 1a2b3c
+    /// ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ url     ^^^^^^ commit
+    ///                               ^^^^^^^ branch
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Ok(Ref { item, commit }) = s.parse::<Ref>();
+
+        let (url, branch) = match item.rsplit_once('#') {
+            Some((url, branch)) => (
+                url,
+                Some(
+                    BranchName::try_new(branch)
+                        .map_err(|err| anyhow!("invalid branch name: {err}"))?,
+                ),
+            ),
+            None => (item.as_str(), None),
+        };
+
+        if url.is_empty() {
+            bail!("Invalid git URL: {s}. Expected format: url#branch");
+        }
+
+        Ok(Self {
+            url: url.to_string(),
+            branch,
+            commit,
+        })
+    }
+}
+
+/// A branch to fetch, either from GitHub or from any git repository
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum BranchRemote {
+    /// e.g. `helix-editor/helix/master @ 1a2b3c`
+    GitHub(Remote),
+    /// e.g. `https://git.sr.ht/~user/helix#feature @ 1a2b3c`
+    Url(GitUrl),
+}
+
+impl BranchRemote {
+    /// Commit that the branch is pinned to
+    pub const fn commit(&self) -> Option<&CommitId> {
+        match self {
+            Self::GitHub(remote) => remote.commit.as_ref(),
+            Self::Url(url) => url.commit.as_ref(),
+        }
+    }
+
+    /// Pin the branch to the `commit`
+    pub fn set_commit(&mut self, commit: Option<CommitId>) {
+        match self {
+            Self::GitHub(remote) => remote.commit = commit,
+            Self::Url(url) => url.commit = commit,
+        }
+    }
+
+    /// The branch without its commit, e.g. `helix-editor/helix/master`
+    pub fn name(&self) -> String {
+        match self {
+            Self::GitHub(remote) => Remote {
+                commit: None,
+                ..remote.clone()
+            }
+            .to_string(),
+            Self::Url(url) => GitUrl {
+                commit: None,
+                ..url.clone()
+            }
+            .to_string(),
+        }
+    }
+
+    /// `true` if both remotes refer to the same branch, regardless of the commit
+    ///
+    /// A remote without a branch refers to the `default_branch` of its repository
+    pub fn is_same_branch(&self, other: &Self, default_branch: Option<&BranchName>) -> bool {
+        match (self, other) {
+            (Self::GitHub(remote), Self::GitHub(other)) => {
+                remote.is_same_branch(other, default_branch)
+            }
+            (Self::Url(url), Self::Url(other)) => url.is_same_branch(other, default_branch),
+            (Self::GitHub(_), Self::Url(_)) | (Self::Url(_), Self::GitHub(_)) => false,
+        }
+    }
+}
+
+impl Display for BranchRemote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::GitHub(remote) => remote.fmt(f),
+            Self::Url(url) => url.fmt(f),
+        }
+    }
+}
+
+impl FromStr for BranchRemote {
+    type Err = anyhow::Error;
+
+    /// Parse either a git URL or `owner/repo/branch`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if GitUrl::is_url(s) {
+            s.parse().map(Self::Url)
+        } else {
+            s.parse().map(Self::GitHub)
+        }
+    }
+}
+
+/// How the commits of an entry are brought into patchy's branch
+#[derive(Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Squash all commits of the entry into a single commit
+    #[default]
+    Squash,
+    /// Create a merge commit, keeping all of the entry's commits
+    Merge,
+    /// Replay each of the entry's commits on top of patchy's branch
+    Rebase,
+}
+
+impl MergeStrategy {
+    /// Name of the strategy, as written in the config
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Squash => "squash",
+            Self::Merge => "merge",
+            Self::Rebase => "rebase",
+        }
+    }
+}
+
+/// Options shared by every entry of `pull-requests` and `branches`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct EntryOptions {
+    /// Why this entry is carried
+    pub note: Option<String>,
+    /// If `false`, the entry is skipped by `patchy run`
+    pub enabled: bool,
+    /// How to merge this entry. If none, uses the `merge-strategy` of the config
+    pub merge_strategy: Option<MergeStrategy>,
+}
+
+impl Default for EntryOptions {
+    fn default() -> Self {
+        Self {
+            note: None,
+            enabled: true,
+            merge_strategy: None,
+        }
+    }
+}
+
+/// Represents a pull request of a repository. E.g. `10000`, or `10000 @ deadbeef`
+///
+/// Can also be written as an inline table:
+///
+/// ```toml
+/// { number = 10000, commit = "deadbeef", note = "...", enabled = false, merge-strategy = "rebase", after = [9999] }
+/// ```
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PullRequest {
+    /// Number of the pull request
+    pub number: PrNumber,
+    /// Commit to checkout of the pull request. If none, uses the latest commit
+    pub commit: Option<CommitId>,
+    /// Options for this pull request
+    pub options: EntryOptions,
+    /// Pull requests which must be merged before this one, e.g. because it is stacked on them
+    pub after: Vec<PrNumber>,
+}
+
+/// Inline table form of [`PullRequest`]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PullRequestTable {
+    /// Number of the pull request
+    number: PrNumber,
+    /// Commit to checkout of the pull request
+    commit: Option<CommitId>,
+    /// Why this pull request is carried
+    note: Option<String>,
+    /// If `false`, the pull request is skipped
+    enabled: Option<bool>,
+    /// How to merge this pull request
+    merge_strategy: Option<MergeStrategy>,
+    /// Pull requests which must be merged before this one
+    #[serde(default, alias = "depends-on")]
+    after: Vec<PrNumber>,
+}
+
+impl Entry for PullRequest {
+    type Table = PullRequestTable;
+
+    fn from_table(table: Self::Table) -> anyhow::Result<Self> {
+        Ok(Self {
+            number: table.number,
+            commit: table.commit,
+            options: EntryOptions::new(table.note, table.enabled, table.merge_strategy),
+            after: table.after,
+        })
+    }
+}
+
+/// A branch in `branches`, e.g. `helix-editor/helix/master @ 1a2b3c`
+/// or `https://git.sr.ht/~user/helix#feature @ 1a2b3c`
+///
+/// Can also be written as an inline table:
+///
+/// ```toml
+/// { remote = "helix-editor/helix/master", commit = "1a2b3c", note = "...", enabled = false }
+/// { url = "https://git.sr.ht/~user/helix", branch = "feature", commit = "1a2b3c" }
+/// ```
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct BranchEntry {
+    /// The branch to merge
+    pub remote: BranchRemote,
+    /// Options for this branch
+    pub options: EntryOptions,
+}
+
+impl FromStr for BranchEntry {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            remote: s.parse()?,
+            options: EntryOptions::default(),
+        })
+    }
+}
+
+/// Inline table form of [`BranchEntry`]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BranchEntryTable {
+    /// The branch, in the same format as the string form
+    remote: Option<String>,
+    /// URL of any git repository, instead of `remote`
+    url: Option<String>,
+    /// Branch of `url` to fetch. If omitted, uses the default branch of the repository
+    branch: Option<BranchName>,
+    /// Commit to checkout of the branch. Overrides the commit in `remote`
+    commit: Option<CommitId>,
+    /// Why this branch is carried
+    note: Option<String>,
+    /// If `false`, the branch is skipped
+    enabled: Option<bool>,
+    /// How to merge this branch
+    merge_strategy: Option<MergeStrategy>,
+}
+
+impl Entry for BranchEntry {
+    type Table = BranchEntryTable;
+
+    fn from_table(table: Self::Table) -> anyhow::Result<Self> {
+        let mut remote = match (table.remote, table.url) {
+            (Some(remote), None) if table.branch.is_none() => remote.parse::<BranchRemote>()?,
+            (Some(_), None) => bail!("`branch` can only be used together with `url`"),
+            (None, Some(url)) => BranchRemote::Url(GitUrl {
+                url,
+                branch: table.branch,
+                commit: None,
+            }),
+            (Some(_), Some(_)) => bail!("a branch can't have both `remote` and `url`"),
+            (None, None) => bail!("a branch needs either `remote` or `url`"),
+        };
+        if table.commit.is_some() {
+            remote.set_commit(table.commit);
+        }
+
+        Ok(Self {
+            remote,
+            options: EntryOptions::new(table.note, table.enabled, table.merge_strategy),
+        })
+    }
+}
+
+impl EntryOptions {
+    /// Create options from the fields of an inline table, filling in the defaults
+    fn new(
+        note: Option<String>,
+        enabled: Option<bool>,
+        merge_strategy: Option<MergeStrategy>,
+    ) -> Self {
+        Self {
+            note,
+            enabled: enabled.unwrap_or(Self::default().enabled),
+            merge_strategy,
+        }
+    }
+}
+
+/// A patch in `patches`, e.g. `remove-tab` for `.patchy/remove-tab.patch`
+///
+/// Can also be written as an inline table, which sets the commit that a plain diff becomes,
+/// or downloads the patch from a URL:
+///
+/// ```toml
+/// { name = "fix.diff", message = "Fix crash on startup", author = "Jane Doe <jane@example.com>" }
+/// { name = "fix-crash", url = "https://example.com/fix-crash.patch", sha256 = "9f86d0..." }
+/// ```
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct Patch {
+    /// File name of the patch, or of a directory with a series of patches
+    pub name: PatchName,
+    /// Message of the commit of a plain diff. If omitted, it is named after the patch
+    pub message: Option<String>,
+    /// Author of the commit of a plain diff, e.g. `Jane Doe <jane@example.com>`.
+    /// If omitted, uses the git user
+    pub author: Option<String>,
+    /// Where the patch is downloaded from. If none, the patch is a file in patchy's config directory
+    pub download: Option<Download>,
+    /// If `false`, the patch is not applied
+    pub enabled: bool,
+}
+
+/// A patch published elsewhere, e.g. in a mailing list archive or a gist
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct Download {
+    /// URL of the patch
+    pub url: String,
+    /// Expected SHA-256 checksum of the patch, as lowercase hex
+    pub sha256: String,
+}
+
+impl Patch {
+    /// Message of the commit of a plain diff
+    pub fn message(&self) -> String {
+        self.message
+            .clone()
+            .unwrap_or_else(|| format!("patchy: Apply patch {}", self.name))
+    }
+}
+
+impl Display for Patch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+impl FromStr for Patch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            name: PatchName::try_new(PathBuf::from(s)).map_err(|_| {
+                anyhow!(
+                    "invalid patch name `{s}`, expected a path inside of `{}`",
+                    ROOT.as_str()
+                )
+            })?,
+            message: None,
+            author: None,
+            download: None,
+            enabled: true,
+        })
+    }
+}
+
+/// Inline table form of [`Patch`]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PatchTable {
+    /// File name of the patch
+    name: PatchName,
+    /// Message of the commit of a plain diff
+    message: Option<String>,
+    /// Author of the commit of a plain diff
+    author: Option<String>,
+    /// URL to download the patch from
+    url: Option<String>,
+    /// Expected SHA-256 checksum of the patch at `url`
+    sha256: Option<String>,
+    /// If `false`, the patch is not applied
+    enabled: Option<bool>,
+}
+
+impl Entry for Patch {
+    type Table = PatchTable;
+
+    fn from_table(table: Self::Table) -> anyhow::Result<Self> {
+        let download = match (table.url, table.sha256) {
+            (Some(url), Some(sha256)) => {
+                if sha256.len() != 64 || !sha256.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                    bail!(
+                        "`sha256` of patch {} must be 64 hexadecimal digits",
+                        table.name
+                    );
+                }
+                Some(Download {
+                    url,
+                    sha256: sha256.to_ascii_lowercase(),
+                })
+            }
+            (Some(url), None) => bail!(
+                "patch {} is downloaded from {url}, so it needs a `sha256` checksum",
+                table.name
+            ),
+            (None, Some(_)) => bail!("`sha256` can only be used together with `url`"),
+            (None, None) => None,
+        };
+
+        Ok(Self {
+            name: table.name,
+            message: table.message,
+            author: table.author,
+            download,
+            enabled: table.enabled.unwrap_or(true),
+        })
+    }
+}
+
+/// An entry of the config which can be written either as a string or as an inline table
+pub trait Entry: FromStr<Err = anyhow::Error> {
+    /// Inline table form of this entry
+    type Table: DeserializeOwned;
+
+    /// Create the entry from its inline table form
+    fn from_table(table: Self::Table) -> anyhow::Result<Self>;
+}
+
+/// Deserializes an [`Entry`] from either a string or an inline table
+struct EntryVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Entry> de::Visitor<'de> for EntryVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a string or an inline table")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let table = T::Table::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        T::from_table(table).map_err(de::Error::custom)
+    }
+}
+
+impl Display for PullRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.number)?;
+        if let Some(commit) = &self.commit {
+            write!(f, " @ {commit}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PullRequest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Ok(Ref {
+            item: pr_number,
+            commit,
+        }) = s.parse::<Ref>();
+
+        let number = pr_number
+            .strip_prefix('#')
+            .unwrap_or(&pr_number)
+            .parse()
+            .map_err(|err| anyhow!("invalid PR number: {pr_number}: {err}"))?;
+
+        Ok(Self {
+            number,
+            commit,
+            options: EntryOptions::default(),
+            after: Vec::new(),
+        })
+    }
+}
+
+/// Represents a branch in git
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Branch {
+    /// Name of the branch
+    pub name: BranchName,
+    /// Commit to checkout when fetching this branch
+    pub commit: Option<CommitId>,
+}
+
+impl FromStr for Branch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Ok(Ref {
+            item: branch_name,
+            commit,
+        }) = s.parse::<Ref>();
+
+        Ok(Self {
+            name: BranchName::try_new(branch_name)?,
+            commit,
+        })
+    }
+}
+
+/// Represents any git item which may be associated with a commit, `<item> @ <commit>`
+/// e.g. `helix-editor/helix/master @ deadbeef`
+#[derive(Debug, Eq, PartialEq)]
+pub struct Ref {
+    /// Git item. E.g. branch, or remote which may associate with the `commit`
+    pub item: String,
+    /// Commit to checkout of the `item`. If none, uses the latest commit
+    pub commit: Option<CommitId>,
+}
+
+impl FromStr for Ref {
+    type Err = Infallible;
+
+    /// Parses user inputs of the form `<head> @ <commit-hash>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split(" @ ").collect();
+
+        let len = parts.len();
+
+        if len == 1 {
+            // The string does not contain the ` @ `, so the user chose to use the latest
+            // commit rather than a specific one
+            Self {
+                item: s.into(),
+                commit: None,
+            }
+        } else {
+            // They want to use a specific commit
+            let head: String = parts
+                .get(0..len - 1)
+                .expect("`0..$.len() - 1` is all but the last elemenmt")
+                .iter()
+                .map(|s| String::from(*s))
+                .collect();
+            let commit = (parts
+                .last()
+                .expect("`parts` is always non-empty, even if the split pattern does not match")
+                .to_owned())
+            .parse::<CommitId>()
+            .ok();
+            Self { item: head, commit }
+        }
+        .pipe(Ok)
+    }
+}
+
+/// Number of a pull request
+#[nutype(
+    validate(greater = 0),
+    derive(
+        Eq,
+        PartialEq,
+        Display,
+        Debug,
+        FromStr,
+        Copy,
+        Clone,
+        TryFrom,
+        Deserialize,
+        Serialize
+    )
+)]
+pub struct PrNumber(u32);
+
+/// Represents owner of a repository
+///
+/// E.g. in `helix-editor/helix/master`, this is `helix-editor`
+#[nutype(
+    validate(not_empty),
+    derive(
+        Debug, Eq, PartialEq, Ord, PartialOrd, Clone, AsRef, Display, Serialize, TryFrom
+    )
+)]
+pub struct RepoOwner(String);
+
+/// Represents name of a repository
+///
+/// E.g. in `helix-editor/helix/master`, this is `helix`
+#[nutype(
+    validate(not_empty),
+    derive(
+        Debug, Eq, PartialEq, Ord, PartialOrd, Clone, AsRef, Display, Serialize, TryFrom
+    )
+)]
+pub struct RepoName(String);
+
+/// Name of a branch in git
+///
+/// E.g. in `helix-editor/helix/master`, this is `master`
+#[nutype(
+    validate(not_empty),
+    derive(
+        Debug, Eq, PartialEq, Ord, PartialOrd, Clone, AsRef, Display, Serialize, TryFrom
+    )
+)]
+pub struct BranchName(String);
+
+impl FromStr for BranchName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_new(s).map_err(|err| match err {
+            BranchNameError::NotEmptyViolated => "branch name cannot be empty".to_string(),
+        })
+    }
+}
+
+/// File name of a patch, relative to patchy's config directory
+#[nutype(
+    validate(predicate = is_valid_patch_name),
+    derive(Hash, Eq, PartialEq, Debug, AsRef, Deserialize, Clone, FromStr, TryFrom)
+)]
+pub struct PatchName(PathBuf);
+
+impl TryFrom<&str> for PatchName {
+    type Error = PatchNameError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        PatchName::try_new(PathBuf::from(value))
+    }
+}
+
+impl Display for PatchName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref().display())
+    }
+}
+
+/// Represents a git commit hash
+#[nutype(
+    validate(not_empty, predicate = is_valid_commit_hash),
+    derive(
+        Debug, Eq, PartialEq, Ord, PartialOrd, Clone, AsRef, TryFrom, FromStr, Display, Deserialize,
+        Serialize
+    )
+)]
+pub struct CommitId(String);
+
+/// Does not check if the commit exists, just checks if it is potentially valid
+///
+/// A patch name is a relative path inside of patchy's config directory,
+/// e.g. `fix.patch` or `series/0001-fix.patch`, but not `../fix.patch` or `/tmp/fix.patch`
+pub fn is_valid_patch_name(name: &Path) -> bool {
+    !name.as_os_str().is_empty()
+        && name
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// A commit hash can consist of `a-f` and `0-9` characters
+pub fn is_valid_commit_hash(hash: &str) -> bool {
+    hash.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+/// Implement `Deserialize` for these types, given that they have a `FromStr` impl
+// This is not a blanket impl as that would violate the orphan rule
+macro_rules! impl_deserialize_for {
+    ($($ty:ty)*) => {
+        $(
+            impl<'de> serde::Deserialize<'de> for $ty {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+                {
+                    String::deserialize(deserializer)?
+                        .parse::<Self>()
+                        .map_err(serde::de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+impl_deserialize_for!(Remote BranchRemote Ref Branch BranchName);
+
+/// Implement `Deserialize` for these [`Entry`] types
+macro_rules! impl_deserialize_for_entry {
+    ($($ty:ty)*) => {
+        $(
+            impl<'de> serde::Deserialize<'de> for $ty {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+                {
+                    deserializer.deserialize_any(EntryVisitor(PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+impl_deserialize_for_entry!(PullRequest BranchEntry Patch);
+
+pub mod backup {
+    //! Backup files in patchy's config directory
+
+    use crate::git;
+
+    use super::PATH;
+    use anyhow::{Result, anyhow};
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    /// Backup for a single file
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct FileBackup {
+        /// Path of the file to backup, relative to the `.patchy` config directory
+        filename: PathBuf,
+        /// Contents of the backed up file, which need not be UTF-8, e.g. binary patches
+        contents: Vec<u8>,
+    }
+
+    impl FileBackup {
+        /// Path of the file, relative to the `.patchy` config directory
+        pub fn filename(&self) -> &Path {
+            &self.filename
+        }
+    }
+
+    /// Restore the backed up files
+    pub fn restore(files: &[FileBackup]) -> Result<()> {
+        for FileBackup { filename, contents } in files {
+            let path = git::ROOT.join(PathBuf::from(super::ROOT.as_str()).join(filename));
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|err| anyhow!("failed to restore backup: {err}"))?;
+            }
+
+            fs::write(&path, contents).map_err(|err| anyhow!("failed to restore backup: {err}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Back up the files in patchy's config directory which were changed since the `files`
+    /// were backed up, or which were created since
+    pub fn changed(files: &[FileBackup]) -> Result<Vec<FileBackup>> {
+        Ok(backup()?
+            .into_iter()
+            .filter(|file| !files.contains(file))
+            .collect())
+    }
+
+    /// Backup all files in patchy's config directory, including the ones in subdirectories
+    pub fn backup() -> Result<Vec<FileBackup>> {
+        let mut backed_up_files = Vec::new();
+
+        backup_dir(Path::new(""), &mut backed_up_files)?;
+
+        Ok(backed_up_files)
+    }
+
+    /// Backup all files in the directory `dir`, relative to patchy's config directory
+    fn backup_dir(dir: &Path, backed_up_files: &mut Vec<FileBackup>) -> Result<()> {
+        let config_files = fs::read_dir(PATH.join(dir)).map_err(|err| {
+            anyhow!(
+                "Failed to read files in directory `{}`:\n{err}",
+                PATH.join(dir).display()
+            )
+        })?;
+
+        for config_file in config_files.flatten() {
+            let filename = dir.join(config_file.file_name());
+
+            if config_file
+                .file_type()
+                .is_ok_and(|file_type| file_type.is_dir())
+            {
+                backup_dir(&filename, backed_up_files)?;
+                continue;
+            }
+
+            let file_backup = fs::read(config_file.path())
+                .map_err(|err| anyhow!("{err}"))
+                .map(|contents| FileBackup {
+                    filename: filename.clone(),
+                    contents,
+                })
+                .map_err(|err| {
+                    anyhow!(
+                        "failed to backup patchy config file {} for configuration files:\n{err}",
+                        filename.display()
+                    )
+                })?;
+
+            backed_up_files.push(file_backup);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::indexset;
+
+    use super::*;
+
+    #[test]
+    fn parse_patch_name() {
+        for name in ["fix", "fix.patch", "series/0001-fix.patch", "./fix.diff"] {
+            name.parse::<Patch>().expect(name);
+        }
+        for name in ["", "../fix.patch", "series/../../fix", "/tmp/fix.patch"] {
+            name.parse::<Patch>().expect_err(name);
+        }
+
+        toml::from_str::<Patch>(r#"name = "../fix.patch""#).unwrap_err();
+    }
+
+    #[test]
+    fn parse_remote() {
+        let cases = [
+            (
+                "helix-editor/helix/master @ 1a2b3c",
+                Remote {
+                    owner: "helix-editor".try_into().unwrap(),
+                    repo: "helix".try_into().unwrap(),
+                    branch: Some("master".try_into().unwrap()),
+                    commit: Some("1a2b3c".try_into().unwrap()),
+                },
+            ),
+            (
+                "helix-editor/helix @ deadbeef",
+                Remote {
+                    owner: "helix-editor".try_into().unwrap(),
+                    repo: "helix".try_into().unwrap(),
+                    branch: None,
+                    commit: Some("deadbeef".try_into().unwrap()),
+                },
+            ),
+            (
+                "helix-editor/helix/feat/feature-x @ abc123",
+                Remote {
+                    owner: "helix-editor".try_into().unwrap(),
+                    repo: "helix".try_into().unwrap(),
+                    branch: Some("feat/feature-x".try_into().unwrap()),
+                    commit: Some("abc123".try_into().unwrap()),
+                },
+            ),
+            (
+                "owner/repo/branch",
+                Remote {
+                    owner: "owner".try_into().unwrap(),
+                    repo: "repo".try_into().unwrap(),
+                    branch: Some("branch".try_into().unwrap()),
+                    commit: None,
+                },
+            ),
+            (
+                "owner/repo",
+                Remote {
+                    owner: "owner".try_into().unwrap(),
+                    repo: "repo".try_into().unwrap(),
+                    branch: None,
+                    commit: None,
+                },
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let result = Remote::from_str(input);
+            assert_eq!(result.unwrap(), expected, "input: {input:?}",);
+        }
+    }
+
+    #[test]
+    fn parse_branch_remote() {
+        let url = |url: &str, branch: Option<&str>, commit: Option<&str>| {
+            BranchRemote::Url(GitUrl {
+                url: url.to_string(),
+                branch: branch.map(|branch| branch.try_into().unwrap()),
+                commit: commit.map(|commit| commit.try_into().unwrap()),
+            })
+        };
+
+        let cases = [
+            (
+                "https://git.sr.ht/~user/helix#feat/x @ 1a2b3c",
+                url(
+                    "https://git.sr.ht/~user/helix",
+                    Some("feat/x"),
+                    Some("1a2b3c"),
+                ),
+            ),
+            (
+                "ssh://git@example.com:2222/user/helix.git",
+                url("ssh://git@example.com:2222/user/helix.git", None, None),
+            ),
+            (
+                "git@example.com:user/helix.git#main",
+                url("git@example.com:user/helix.git", Some("main"), None),
+            ),
+            (
+                "file:///tmp/helix @ deadbeef",
+                url("file:///tmp/helix", None, Some("deadbeef")),
+            ),
+            ("../helix#main", url("../helix", Some("main"), None)),
+            (
+                "helix-editor/helix/master",
+                BranchRemote::GitHub("helix-editor/helix/master".parse().unwrap()),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                input.parse::<BranchRemote>().unwrap(),
+                expected,
+                "input: {input:?}"
+            );
+            assert_eq!(expected.to_string(), input, "display roundtrips");
+        }
+
+        assert_eq!(
+            "https://example.com/user/helix.git/"
+                .parse::<GitUrl>()
+                .unwrap()
+                .repo_name(),
+            "helix"
+        );
+        assert_eq!(
+            "git@example.com:helix"
+                .parse::<GitUrl>()
+                .unwrap()
+                .repo_name(),
+            "helix"
+        );
+        "https://example.com/helix#".parse::<GitUrl>().unwrap_err();
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = r#"
+repo = "helix-editor/helix"
+remote-branch = "master @ a1b2c4"
+
+local-branch = "patchy"
+
+pull-requests = ["10000", "10000", "454 @ a1b2c3", "1 @ a1b2c3"]
+
+patches = ['remove-tab']"#;
+
+        let conf = toml::from_str::<Config>(config).unwrap();
+
+        pretty_assertions::assert_eq!(
+            conf,
+            Config {
+                local_branch: "patchy".try_into().unwrap(),
+                patches: indexset!["remove-tab".parse().unwrap()],
+                pull_requests: vec![
+                    PullRequest {
+                        number: 10000.try_into().unwrap(),
+                        commit: None,
+                        options: EntryOptions::default(),
+                        after: vec![],
+                    },
+                    PullRequest {
+                        number: 10000.try_into().unwrap(),
+                        commit: None,
+                        options: EntryOptions::default(),
+                        after: vec![],
+                    },
+                    PullRequest {
+                        number: 454.try_into().unwrap(),
+                        commit: Some("a1b2c3".try_into().unwrap()),
+                        options: EntryOptions::default(),
+                        after: vec![],
+                    },
+                    PullRequest {
+                        number: 1.try_into().unwrap(),
+                        commit: Some("a1b2c3".try_into().unwrap()),
+                        options: EntryOptions::default(),
+                        after: vec![],
+                    },
+                ],
+                branches: vec![],
+                remote_branch: Some(Branch {
+                    name: "master".try_into().unwrap(),
+                    commit: Some("a1b2c4".try_into().unwrap())
+                }),
+                repo: "helix-editor/helix".to_string(),
+                profiles: IndexMap::new(),
+                merge_strategy: MergeStrategy::Squash,
+                use_gh_cli: false,
+                github_host: None,
+                github_token: None,
+                push_remote: None,
+                pull_request_queries: vec![],
+                base_remote: None,
+                commit_message: CommitMessage::default(),
+                committer: Committer::default(),
+                hooks: Hooks::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_hooks() {
+        let config = r#"
+repo = "helix-editor/helix"
+local-branch = "patchy"
+
+[hooks]
+after-entry = ["cargo check"]
+after-all = ["cargo test"]
+policy = "drop""#;
+
+        pretty_assertions::assert_eq!(
+            toml::from_str::<Config>(config).unwrap().hooks,
+            Hooks {
+                after_base: vec![],
+                after_entry: vec!["cargo check".to_string()],
+                after_all: vec!["cargo test".to_string()],
+                policy: HookPolicy::Drop,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_committer() {
+        let config = r#"
+repo = "helix-editor/helix"
+local-branch = "patchy"
+
+[committer]
+name = "fork-bot"
+email = "bot@example.com"
+sign-off = false
+sign = "ssh"
+signing-key = "~/.ssh/id_ed25519.pub""#;
+
+        pretty_assertions::assert_eq!(
+            toml::from_str::<Config>(config).unwrap().committer,
+            Committer {
+                name: Some("fork-bot".to_string()),
+                email: Some("bot@example.com".to_string()),
+                sign_off: Some(false),
+                sign: Some(SignatureFormat::Ssh),
+                signing_key: Some("~/.ssh/id_ed25519.pub".to_string()),
+            }
+        );
+
+        toml::from_str::<Config>(
+            r#"
+repo = "helix-editor/helix"
+local-branch = "patchy"
+committer = { sign = "x509" }"#,
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn format_commit_message() {
+        let config = r#"
+repo = "helix-editor/helix"
+local-branch = "patchy"
+
+[commit-message]
+pull-request = "Merge #{number}: {title}"
+trailers = ["Patchy-PR: {number}", "Co-authored-by: {author} <{author-email}>"]"#;
+
+        let conf = toml::from_str::<Config>(config).unwrap();
+        let values = [
+            ("number", "12254"),
+            ("title", "Add file explorer"),
+            ("author", "nik-rev"),
+            ("author-email", "1+nik-rev@users.noreply.github.com"),
+        ];
+
+        assert_eq!(
+            conf.commit_message.pull_request(&values).to_string(),
+            "Merge #12254: Add file explorer
+
+Patchy-PR: 12254
+Co-authored-by: nik-rev <1+nik-rev@users.noreply.github.com>"
+        );
+
+        let no_trailers = CommitMessage {
+            trailers: vec![],
+            ..CommitMessage::default()
+        };
+        assert_eq!(
+            no_trailers.pull_request(&values).to_string(),
+            "patchy: Merge pull request #12254 Add file explorer",
+            "no empty line without trailers"
+        );
+
+        assert_eq!(
+            CommitMessage::default()
+                .branch(&[("branch", "helix-editor/helix/master"), ("head", "1a2b3c")])
+                .to_string(),
+            "patchy: Merge branch helix-editor/helix/master
+
+Patchy-Branch: helix-editor/helix/master
+Patchy-Head: 1a2b3c"
+        );
+    }
+
+    #[test]
+    fn expand_placeholders_once() {
+        let values = [
+            ("title", "Expand {author-email} and {title}"),
+            ("author-email", "1+nik-rev@users.noreply.github.com"),
+        ];
+
+        assert_eq!(
+            expand("{title} by {author-email}, {unknown} {", &values),
+            "Expand {author-email} and {title} by 1+nik-rev@users.noreply.github.com, {unknown} {",
+            "placeholders inside of the values are not expanded"
+        );
+    }
+
+    #[test]
+    fn parse_config_entry_tables() {
+        let config = r#"
+repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+
+pull-requests = [
+  "10000",
+  { number = 454, commit = "a1b2c3", note = "file explorer", enabled = false, merge-strategy = "rebase" },
+  { number = 1, after = [454] },
+  { number = 2, depends-on = [1, 454] },
+]
+
+branches = [
+  "helix-editor/helix/master",
+  { remote = "owner/repo/feat @ deadbeef", commit = "a1b2c3", merge-strategy = "merge" },
+  { url = "https://git.sr.ht/~user/helix", branch = "feat", note = "not on GitHub" },
+  "/home/user/helix @ a1b2c3",
+]
+
+patches = [
+  "remove-tab",
+  { name = "fix.diff", message = "Fix crash", author = "Jane Doe <jane@example.com>", enabled = false },
+  { name = "gist", url = "https://example.com/gist.patch", sha256 = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08" },
+]"#;
+
+        let conf = toml::from_str::<Config>(config).unwrap();
+
+        pretty_assertions::assert_eq!(
+            conf.pull_requests,
+            vec![
+                PullRequest {
+                    number: 10000.try_into().unwrap(),
+                    commit: None,
+                    options: EntryOptions::default(),
+                    after: vec![],
+                },
+                PullRequest {
+                    number: 454.try_into().unwrap(),
+                    commit: Some("a1b2c3".try_into().unwrap()),
+                    options: EntryOptions {
+                        note: Some("file explorer".to_string()),
+                        enabled: false,
+                        merge_strategy: Some(MergeStrategy::Rebase),
+                    },
+                    after: vec![],
+                },
+                PullRequest {
+                    number: 1.try_into().unwrap(),
+                    commit: None,
+                    options: EntryOptions::default(),
+                    after: vec![454.try_into().unwrap()],
+                },
+                PullRequest {
+                    number: 2.try_into().unwrap(),
+                    commit: None,
+                    options: EntryOptions::default(),
+                    after: vec![1.try_into().unwrap(), 454.try_into().unwrap()],
+                },
+            ]
+        );
+        pretty_assertions::assert_eq!(
+            conf.branches,
+            vec![
+                BranchEntry {
+                    remote: "helix-editor/helix/master".parse().unwrap(),
+                    options: EntryOptions::default(),
+                },
+                BranchEntry {
+                    remote: "owner/repo/feat @ a1b2c3".parse().unwrap(),
+                    options: EntryOptions {
+                        merge_strategy: Some(MergeStrategy::Merge),
+                        ..EntryOptions::default()
+                    },
+                },
+                BranchEntry {
+                    remote: "https://git.sr.ht/~user/helix#feat".parse().unwrap(),
+                    options: EntryOptions {
+                        note: Some("not on GitHub".to_string()),
+                        ..EntryOptions::default()
+                    },
+                },
+                BranchEntry {
+                    remote: BranchRemote::Url(GitUrl {
+                        url: "/home/user/helix".to_string(),
+                        branch: None,
+                        commit: Some("a1b2c3".try_into().unwrap()),
+                    }),
+                    options: EntryOptions::default(),
+                },
+            ]
+        );
+        pretty_assertions::assert_eq!(
+            conf.patches,
+            indexset![
+                "remove-tab".parse().unwrap(),
+                Patch {
+                    name: "fix.diff".try_into().unwrap(),
+                    message: Some("Fix crash".to_string()),
+                    author: Some("Jane Doe <jane@example.com>".to_string()),
+                    download: None,
+                    enabled: false,
+                },
+                Patch {
+                    name: "gist".try_into().unwrap(),
+                    message: None,
+                    author: None,
+                    download: Some(Download {
+                        url: "https://example.com/gist.patch".to_string(),
+                        sha256: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                            .to_string(),
+                    }),
+                    enabled: true,
+                },
+            ]
+        );
+
+        let err = toml::from_str::<Config>(
+            r#"
+repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+pull-requests = [{ number = 1, enable = false }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.message().contains("unknown field `enable`"),
+            "typos in entry tables are reported: {err}"
+        );
+
+        toml::from_str::<Config>(
+            r#"
+repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+branches = [{ remote = "owner/repo", url = "https://example.com/repo" }]"#,
+        )
+        .unwrap_err();
+
+        let err = toml::from_str::<Config>(
+            r#"
+repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+patches = [{ name = "gist", url = "https://example.com/gist.patch" }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.message().contains("needs a `sha256` checksum"),
+            "downloaded patches must be pinned: {err}"
+        );
+    }
+
+    #[test]
+    fn apply_profile() {
+        let config = r#"
+repo = "helix-editor/helix"
+remote-branch = "master"
+local-branch = "patchy"
+pull-requests = ["10000", "454"]
+branches = ["helix-editor/helix/feat @ a1b2c3", "owner/repo/branch"]
+patches = ["remove-tab", "swap-colors"]
+
+[profiles.stable]
+local-branch = "stable"
+remove-pull-requests = [454]
+remove-branches = ["helix-editor/helix/feat"]
+remove-patches = ["swap-colors"]
+
+[profiles.bleeding]
+local-branch = "bleeding"
+pull-requests = ["12309", "454 @ a1b2c3"]
+branches = ["owner/repo/branch @ d4e5f6"]
+patches = ["experimental"]"#;
+
+        let conf = toml::from_str::<Config>(config).unwrap();
+
+        let stable = toml::from_str::<Config>(config)
+            .unwrap()
+            .with_profile("stable")
+            .unwrap();
+        assert_eq!(stable.local_branch, "stable".try_into().unwrap());
+        assert_eq!(
+            stable.pull_requests,
+            vec!["10000".parse().unwrap()],
+            "454 is removed"
+        );
+        assert_eq!(
+            stable.branches,
+            vec!["owner/repo/branch".parse().unwrap()],
+            "branch is removed regardless of its commit"
+        );
+        assert_eq!(stable.patches, indexset!["remove-tab".parse().unwrap()]);
+
+        let bleeding = conf.with_profile("bleeding").unwrap();
+        assert_eq!(bleeding.local_branch, "bleeding".try_into().unwrap());
+        assert_eq!(
+            bleeding.pull_requests,
+            vec![
+                "10000".parse().unwrap(),
+                "454 @ a1b2c3".parse().unwrap(),
+                "12309".parse().unwrap()
+            ],
+            "454 of the profile replaces the shared one"
+        );
+        assert_eq!(
+            bleeding.branches,
+            vec![
+                "helix-editor/helix/feat @ a1b2c3".parse().unwrap(),
+                "owner/repo/branch @ d4e5f6".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            bleeding.patches,
+            indexset![
+                "remove-tab".parse().unwrap(),
+                "swap-colors".parse().unwrap(),
+                "experimental".parse().unwrap()
+            ]
+        );
+
+        toml::from_str::<Config>(config)
+            .unwrap()
+            .with_profile("nightly")
+            .unwrap_err();
+    }
+}
@@ -1,14 +1,56 @@
 //! Low-level API for git
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use std::{
     env, io,
     path::{Path, PathBuf},
     process::{self, Output},
-    sync::LazyLock,
+    sync::{
+        LazyLock, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
-use crate::config::{BranchName, CommitId};
+use crate::config::{BranchName, CommitId, Committer};
+
+/// Options passed to every git command, e.g. `-c user.name=fork-bot`. Set by [`configure_commits`]
+static OPTIONS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// If patches that patchy applies get a `Signed-off-by` trailer. Set by [`configure_commits`]
+static SIGN_OFF_PATCHES: AtomicBool = AtomicBool::new(true);
+
+/// If the other commits that patchy creates, e.g. merges, get a `Signed-off-by` trailer.
+/// Set by [`configure_commits`]
+static SIGN_OFF_COMMITS: AtomicBool = AtomicBool::new(false);
+
+/// Use the identity and signing of the `committer` for every commit that patchy creates
+pub fn configure_commits(committer: &Committer) {
+    let mut options = Vec::new();
+    let mut set = |key: &str, value: &str| {
+        options.push("-c".to_string());
+        options.push(format!("{key}={value}"));
+    };
+
+    if let Some(name) = &committer.name {
+        set("user.name", name);
+    }
+    if let Some(email) = &committer.email {
+        set("user.email", email);
+    }
+    if let Some(format) = committer.sign {
+        set("commit.gpgSign", "true");
+        set("gpg.format", format.as_str());
+    }
+    if let Some(key) = &committer.signing_key {
+        set("user.signingKey", key);
+    }
+
+    *OPTIONS
+        .write()
+        .expect("no thread panics while holding the lock") = options;
+    SIGN_OFF_PATCHES.store(committer.sign_off.unwrap_or(true), Ordering::Relaxed);
+    SIGN_OFF_COMMITS.store(committer.sign_off.unwrap_or(false), Ordering::Relaxed);
+}
 
 /// Add the file
 pub fn add(file: &str) -> Result<String> {
@@ -25,16 +67,162 @@ pub fn get_message_of_commit(commit: &str) -> Result<String> {
     git(["log", "--format=%B", "--max-count=1", commit])
 }
 
+/// Contents of the file at `path`, relative to the root of the repository, in the commit `object`
+pub fn show_file(object: &str, path: &str) -> Result<String> {
+    git(["show", &format!("{object}:{path}")])
+}
+
+/// Exact bytes of the file at `path`, relative to the root of the repository, in the commit `object`
+///
+/// Unlike [`show_file`], the contents are neither trimmed nor required to be UTF-8
+pub fn show_file_bytes(object: &str, path: &str) -> Result<Vec<u8>> {
+    let args = ["show", &format!("{object}:{path}")];
+    log::debug!("$ git {}", args.join(" "));
+    let output = spawn_git(&args, &ROOT)?;
+    get_git_output(&output, &args)?;
+    Ok(output.stdout)
+}
+
+/// Each commit of the `range` formatted with the `format`, one per line
+pub fn log(range: &str, format: &str) -> Result<String> {
+    git(["log", &format!("--format={format}"), range])
+}
+
+/// Number of commits in the `range`, e.g. `a1b2c3..master`
+pub fn count_commits(range: &str) -> Result<usize> {
+    git(["rev-list", "--count", range])?
+        .parse()
+        .map_err(|err| anyhow!("invalid number of commits: {err}"))
+}
+
 /// Merge the branch into the current one
 pub fn merge(branch: &str) -> Result<String> {
     git(["merge", "--squash", branch])
 }
 
+/// Merge the branch into the current one, creating a merge commit with the `message`
+pub fn merge_commit(branch: &str, message: &str) -> Result<String> {
+    git_signed_off(
+        [
+            "merge",
+            "--no-ff",
+            "--no-edit",
+            "--message",
+            message,
+            branch,
+        ],
+        &SIGN_OFF_COMMITS,
+    )
+}
+
+/// Commit the changes that [`merge`] squashed, with the `message`
+pub fn squash_commit(message: &str) -> Result<String> {
+    git_signed_off(["commit", "--message", message], &SIGN_OFF_COMMITS)
+}
+
+/// Abort the merge that is in progress
+pub fn abort_merge() -> Result<String> {
+    git(["merge", "--abort"])
+}
+
+/// Replay the commits of `branch` on top of `onto`, leaving `branch` checked out
+///
+/// Each replayed commit gets the `trailers`, e.g. `Patchy-PR: 12254`
+pub fn rebase(onto: &str, branch: &str, trailers: &[String]) -> Result<String> {
+    if trailers.is_empty() {
+        return git_signed_off(["rebase", onto, branch], &SIGN_OFF_COMMITS);
+    }
+
+    // the trailers are in the environment, so the shell which runs `--exec` does not interpret them
+    let exec = ["git commit --amend --no-edit --no-verify".to_string()]
+        .into_iter()
+        .chain((0..trailers.len()).map(|index| format!("--trailer \"$PATCHY_TRAILER_{index}\"")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut args = vec!["rebase", "--exec", &exec, onto, branch];
+    if SIGN_OFF_COMMITS.load(Ordering::Relaxed) {
+        args.insert(1, "--signoff");
+    }
+    log::debug!("$ git {}", args.join(" "));
+
+    let mut command = command(&args, &ROOT);
+    for (index, trailer) in trailers.iter().enumerate() {
+        command.env(format!("PATCHY_TRAILER_{index}"), trailer);
+    }
+
+    get_git_output(&command.output()?, &args)
+}
+
+/// Abort the rebase that is in progress
+pub fn abort_rebase() -> Result<String> {
+    git(["rebase", "--abort"])
+}
+
+/// Fast-forward the current branch to `branch`
+pub fn fast_forward(branch: &str) -> Result<String> {
+    git(["merge", "--ff-only", branch])
+}
+
+/// Resolve `object` to the full hash of a commit
+pub fn rev_parse(object: &str) -> Result<String> {
+    git(["rev-parse", "--verify", &format!("{object}^{{commit}}")])
+}
+
+/// Best common ancestor of the commits `a` and `b`
+pub fn merge_base(a: &str, b: &str) -> Result<String> {
+    git(["merge-base", a, b])
+}
+
+/// Create a commit with the tree of `tree_of` on top of `parent`, without checking it out
+///
+/// The commit is authored by `name` and `email`, and made by the committer of
+/// [`configure_commits`]. Returns the hash of the commit
+pub fn commit_tree(
+    tree_of: &str,
+    parent: &str,
+    message: &str,
+    name: &str,
+    email: &str,
+) -> Result<String> {
+    // `commit-tree` has no `--signoff`
+    let message = if SIGN_OFF_COMMITS.load(Ordering::Relaxed) {
+        let ident = git(["var", "GIT_COMMITTER_IDENT"])?;
+        // the identity is followed by a timestamp and a timezone
+        let committer = ident
+            .rsplitn(3, ' ')
+            .nth(2)
+            .ok_or_else(|| anyhow!("invalid committer identity: {ident}"))?;
+        format!("{message}\n\nSigned-off-by: {committer}")
+    } else {
+        message.to_string()
+    };
+
+    let tree = format!("{tree_of}^{{tree}}");
+    let args = ["commit-tree", &tree, "-p", parent, "-m", &message];
+    log::debug!("$ git {}", args.join(" "));
+
+    let output = command(&args, &ROOT)
+        .env("GIT_AUTHOR_NAME", name)
+        .env("GIT_AUTHOR_EMAIL", email)
+        .output()?;
+
+    get_git_output(&output, &args)
+}
+
 /// Remote the given remote
 pub fn remove_remote(remote: &str) -> Result<String> {
     git(["remote", "remove", remote])
 }
 
+/// Files which are neither tracked nor ignored, relative to the root of the repository
+pub fn untracked_files() -> Result<Vec<String>> {
+    Ok(git(["ls-files", "--others", "--exclude-standard", "-z"])?
+        .split('\0')
+        .filter(|file| !file.is_empty())
+        .map(ToString::to_string)
+        .collect())
+}
+
 /// Checkout the commit
 pub fn checkout(object: &str) -> Result<String> {
     git(["checkout", object])
@@ -42,7 +230,10 @@ pub fn checkout(object: &str) -> Result<String> {
 
 /// Create a commit with the given message
 pub fn commit(message: &str) -> Result<String> {
-    git(["commit", "--message", &format!("patchy: {message}")])
+    git_signed_off(
+        ["commit", "--message", &format!("patchy: {message}")],
+        &SIGN_OFF_COMMITS,
+    )
 }
 
 /// Fetch remote `url` to local `name`
@@ -59,6 +250,35 @@ pub fn fetch_remote_branch(
     git(["fetch", url, &format!("{remote_branch}:{local_branch}")])
 }
 
+/// Fetches the `commit` from the repository at `url`, even if it is no longer on a branch
+pub fn fetch_commit(url: &str, commit: &str) -> Result<String> {
+    git(["fetch", url, commit])
+}
+
+/// Lists the commit that `reference` points to in the repository at `url`
+pub fn ls_remote(url: &str, reference: &str) -> Result<String> {
+    git(["ls-remote", url, reference])
+}
+
+/// Default branch of the repository at `url`, which can also be the name of a remote
+pub fn default_branch(url: &str) -> Result<BranchName> {
+    let output = git(["ls-remote", "--symref", url, "HEAD"])?;
+
+    // the first line is e.g. `ref: refs/heads/master\tHEAD`
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("ref: refs/heads/"))
+        .and_then(|line| line.split_once('\t'))
+        .map(|(branch, _)| branch)
+        .ok_or_else(|| anyhow!("`HEAD` of {url} is not a branch"))
+        .and_then(|branch| BranchName::try_new(branch).map_err(|err| anyhow!("{err}")))
+}
+
+/// Names of all remotes of the repository
+pub fn remotes() -> Result<Vec<String>> {
+    git(["remote"]).map(|remotes| remotes.lines().map(str::to_owned).collect())
+}
+
 /// Formats the commit as a `patch` and saves it to the specified path
 pub fn save_commit_as_patch(commit: &CommitId, output_path: &str) -> Result<String> {
     git([
@@ -70,19 +290,178 @@ pub fn save_commit_as_patch(commit: &CommitId, output_path: &str) -> Result<Stri
     ])
 }
 
+/// Formats the commits after `from` up to and including `to` as a numbered series of patches
+/// in the `output_dir`
+///
+/// Returns the paths of the patches
+pub fn save_range_as_patches(from: &CommitId, to: &CommitId, output_dir: &str) -> Result<String> {
+    git([
+        "format-patch",
+        &format!("{from}..{to}"),
+        "--output-directory",
+        output_dir,
+    ])
+}
+
+/// Formats the last `count` commits as a numbered series of patches in the `output_dir`
+///
+/// Returns the paths of the patches
+pub fn save_last_commits_as_patches(count: usize, output_dir: &str) -> Result<String> {
+    git([
+        "format-patch",
+        &format!("-{count}"),
+        "HEAD",
+        "--output-directory",
+        output_dir,
+    ])
+}
+
+/// Resets the current branch and the worktree to the `commit`
+pub fn reset_hard(commit: &str) -> Result<String> {
+    git(["reset", "--hard", commit])
+}
+
+/// Formats the commits after `commit` as a mailbox of patches, and returns it
+pub fn format_patches_since(commit: &str) -> Result<String> {
+    git(["format-patch", "--stdout", &format!("{commit}..HEAD")])
+}
+
+/// Diff between the `commit` and the current commit
+pub fn diff_since(commit: &str) -> Result<String> {
+    git(["diff", commit, "HEAD"])
+}
+
 /// Obtain the URL for a remote
 pub fn get_remote_url(remote: &str) -> Result<String> {
     git(["remote", "get-url", remote])
 }
 
+/// How a patch was applied by [`apply_patch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Applied {
+    /// The patch applied as is
+    Cleanly,
+    /// The patch did not apply as is, but a three-way merge with the blobs it records succeeded
+    ThreeWay,
+    /// The patch did not create a commit, because the current branch already contains its changes
+    Empty,
+    /// The patch could not be applied. The `git am` session is still in progress,
+    /// so the conflict has to be resolved with [`continue_am`] or discarded with [`abort_am`]
+    Conflict {
+        /// Errors of `git am` for each hunk which failed to apply, e.g. `patch failed: src/main.rs:12`
+        hunks: Vec<String>,
+        /// Files which contain conflicts after the three-way merge
+        files: Vec<String>,
+    },
+}
+
 /// Apply a `patch` as a commit
-pub fn apply_patch(filename: &Path) -> Result<()> {
-    if let Err(err) = git(["am", "--keep-cr", "--signoff", &filename.to_string_lossy()]) {
-        git(["am", "--abort"])?;
-        return Err(err);
+///
+/// If the patch does not apply, retries with a three-way merge
+pub fn apply_patch(filename: &Path) -> Result<Applied> {
+    let filename = filename.to_string_lossy();
+    let before = rev_parse("HEAD")?;
+    let applied = |applied| {
+        rev_parse("HEAD").map(|after| {
+            if after == before {
+                Applied::Empty
+            } else {
+                applied
+            }
+        })
+    };
+
+    let Err(err) = git_signed_off(["am", "--keep-cr", &filename], &SIGN_OFF_PATCHES) else {
+        return applied(Applied::Cleanly);
+    };
+    // `git am` does not start a session if it can't read the patch at all
+    if is_am_in_progress() {
+        abort_am()?;
     }
 
-    Ok(())
+    if git_signed_off(["am", "--3way", "--keep-cr", &filename], &SIGN_OFF_PATCHES).is_ok() {
+        return applied(Applied::ThreeWay);
+    }
+
+    Ok(Applied::Conflict {
+        hunks: error_lines(&err),
+        files: conflicted_files()?,
+    })
+}
+
+/// Apply a plain diff, which is not a mailbox, and commit it with the `message` and `author`
+///
+/// If the diff does not apply, retries with a three-way merge. On conflicts, the conflicting
+/// changes are left in the index to be committed with [`commit_as`] or discarded with [`reset_hard`]
+pub fn apply_diff(filename: &Path, message: &str, author: Option<&str>) -> Result<Applied> {
+    let filename = filename.to_string_lossy();
+
+    let applied = match git(["apply", "--index", &filename]) {
+        Ok(_) => Applied::Cleanly,
+        Err(err) => {
+            if git(["apply", "--3way", &filename]).is_err() {
+                return Ok(Applied::Conflict {
+                    hunks: error_lines(&err),
+                    files: conflicted_files()?,
+                });
+            }
+            Applied::ThreeWay
+        }
+    };
+
+    if !is_worktree_dirty() {
+        return Ok(Applied::Empty);
+    }
+
+    commit_as(message, author)?;
+
+    Ok(applied)
+}
+
+/// Commit a patch with the given `message` and `author`, e.g. `Jane Doe <jane@example.com>`
+pub fn commit_as(message: &str, author: Option<&str>) -> Result<String> {
+    match author {
+        Some(author) => git_signed_off(
+            ["commit", "--message", message, "--author", author],
+            &SIGN_OFF_PATCHES,
+        ),
+        None => git_signed_off(["commit", "--message", message], &SIGN_OFF_PATCHES),
+    }
+}
+
+/// Errors reported by a failed git command, without the `error: ` prefix
+fn error_lines(err: &anyhow::Error) -> Vec<String> {
+    err.to_string()
+        .lines()
+        .filter_map(|line| {
+            line.trim_start_matches("Stderr:")
+                .trim()
+                .strip_prefix("error: ")
+        })
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Files which have unresolved conflicts
+fn conflicted_files() -> Result<Vec<String>> {
+    git(["diff", "--name-only", "--diff-filter=U"])
+        .map(|files| files.lines().map(str::to_owned).collect())
+}
+
+/// Commit the resolution of a conflict, and apply the rest of the patches of `git am`
+pub fn continue_am() -> Result<String> {
+    git(["am", "--continue"])
+}
+
+/// Abort the `git am` session that is in progress
+pub fn abort_am() -> Result<String> {
+    git(["am", "--abort"])
+}
+
+/// `true` if a `git am` session is in progress
+pub fn is_am_in_progress() -> bool {
+    git(["rev-parse", "--git-path", "rebase-apply"])
+        .is_ok_and(|path| ROOT.join(path).join("applying").exists())
 }
 
 /// `true` if there are unstaged changes
@@ -107,6 +486,11 @@ pub fn does_object_exist(branch: &str) -> bool {
     git(["rev-parse", "--verify", branch]).is_err()
 }
 
+/// Removes a branch
+pub fn delete_branch(branch: &BranchName) -> Result<String> {
+    git(["branch", "--delete", "--force", branch.as_ref()])
+}
+
 /// Removes a remote and its branch
 ///
 /// WARNING: Only call this function if the script created
@@ -129,6 +513,12 @@ pub fn rename_branch(old: &str, new: &str) -> Result<String> {
     git(["branch", "--move", "--force", old, new])
 }
 
+/// Forcefully push the local `branch` to the branch of the same name of `remote`.
+/// WARNING: this is a destructive action which erases the remote branch
+pub fn force_push(remote: &str, branch: &str) -> Result<String> {
+    git(["push", "--force", remote, branch])
+}
+
 /// Resets the `branch` to the specified `commit`
 pub fn reset_branch_to_commit(branch: &BranchName, commit: &CommitId) -> Result<String> {
     git(["branch", "--force", branch.as_ref(), commit.as_ref()])
@@ -140,6 +530,19 @@ fn git<const N: usize>(args: [&str; N]) -> Result<String> {
     get_git_output(&spawn_git(&args, &ROOT)?, &args)
 }
 
+/// Run `git` with the given arguments, which create commits that are signed off
+/// if `sign_off` is set. See [`configure_commits`]
+fn git_signed_off<const N: usize>(args: [&str; N], sign_off: &AtomicBool) -> Result<String> {
+    let mut args = Vec::from(args);
+    if sign_off.load(Ordering::Relaxed) {
+        // right after the subcommand
+        args.insert(1, "--signoff");
+    }
+
+    log::debug!("$ git {}", args.join(" "));
+    get_git_output(&spawn_git(&args, &ROOT)?, &args)
+}
+
 /// Get output of the git process
 pub fn get_git_output(output: &Output, args: &[&str]) -> Result<String> {
     if output.status.success() {
@@ -158,26 +561,42 @@ pub fn get_git_output(output: &Output, args: &[&str]) -> Result<String> {
 
 /// Spawn a git process and collect its output
 pub fn spawn_git(args: &[&str], git_dir: &Path) -> Result<Output, io::Error> {
-    process::Command::new("git")
+    command(args, git_dir).output()
+}
+
+/// `git` with the given arguments and the options of [`configure_commits`], run in `git_dir`
+fn command(args: &[&str], git_dir: &Path) -> process::Command {
+    let mut command = process::Command::new("git");
+    command
+        .args(
+            &*OPTIONS
+                .read()
+                .expect("no thread panics while holding the lock"),
+        )
         .args(args)
-        .current_dir(git_dir)
-        .output()
+        .current_dir(git_dir);
+    command
 }
 
 /// Location of the root directory of Git
-pub static ROOT: LazyLock<PathBuf> = LazyLock::new(|| {
-    match env::var("PATCHY_ROOT").or_else(|_| {
-        let current_dir = env::current_dir()?;
-        // traverses until it finds a directory with a .git folder
-        // and reports the path to the directory
-        let args = ["rev-parse", "--show-toplevel"];
-        let root = spawn_git(&args, &current_dir)?;
-        get_git_output(&root, &args)
-    }) {
-        Ok(root) => root.into(),
-        Err(err) => {
-            log::error!("Failed to determine Git root directory.\n{err}");
-            process::exit(1)
-        }
+pub static ROOT: LazyLock<PathBuf> = LazyLock::new(|| match find_root() {
+    Ok(root) => root,
+    Err(err) => {
+        log::error!("Failed to determine Git root directory.\n{err}");
+        process::exit(1)
     }
 });
+
+/// Find the root directory of Git, without exiting if there is none, unlike [`ROOT`]
+pub fn find_root() -> Result<PathBuf> {
+    env::var("PATCHY_ROOT")
+        .or_else(|_| {
+            let current_dir = env::current_dir()?;
+            // traverses until it finds a directory with a .git folder
+            // and reports the path to the directory
+            let args = ["rev-parse", "--show-toplevel"];
+            let root = spawn_git(&args, &current_dir)?;
+            get_git_output(&root, &args)
+        })
+        .map(PathBuf::from)
+}
@@ -1,17 +1,48 @@
 //! GitHub API
 
-use std::process;
+use std::{process, sync::LazyLock};
 
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tap::Pipe as _;
 
 use crate::{
-    config::{BranchName, CommitId, PrNumber, RepoName, RepoOwner},
+    config::{BranchName, CommitId, GitHubSettings, PrNumber, RepoName, RepoOwner},
     git,
+    remote_url::GITHUB_HOST,
     utils::{make_request, normalize_commit_msg, with_uuid},
 };
 use anyhow::{Result, anyhow, bail};
 
+/// Host of GitHub and the token to authenticate with its API, read from the config
+/// when they are first needed, so that e.g. `--help` works with a broken config
+static SETTINGS: LazyLock<GitHubSettings> = LazyLock::new(crate::config::github_settings);
+
+/// Host of GitHub, e.g. `github.com`
+pub fn host() -> String {
+    SETTINGS
+        .github_host
+        .clone()
+        .unwrap_or_else(|| GITHUB_HOST.to_string())
+}
+
+/// URL of the repository `repo` on GitHub, e.g. `https://github.com/helix-editor/helix`
+pub fn repo_url(repo: &str) -> String {
+    format!("https://{}/{repo}", host())
+}
+
+/// Base URL of GitHub's API. GitHub Enterprise serves it at `/api/v3` of its host
+fn api_url() -> String {
+    match SETTINGS.github_host.as_deref() {
+        Some(host) if host != GITHUB_HOST => format!("https://{host}/api/v3"),
+        _ => "https://api.github.com".to_string(),
+    }
+}
+
+/// Token to authenticate with GitHub's API, if there is one
+fn token() -> Option<String> {
+    SETTINGS.github_token.clone()
+}
+
 /// Data returned by GitHub's API for the pull request endpoint per repo
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PrData {
@@ -21,6 +52,45 @@ pub struct PrData {
     pub title: String,
     /// Url to the pull request
     pub html_url: String,
+    /// Author of the pull request
+    pub user: User,
+    /// Branch that the pull request would be merged into
+    pub base: Base,
+    /// `open` or `closed`
+    pub state: String,
+    /// If the pull request was merged upstream
+    #[serde(default)]
+    pub merged: bool,
+    /// If the pull request is a draft
+    #[serde(default)]
+    pub draft: bool,
+}
+
+/// Author of a pull request (returned by github api)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct User {
+    /// e.g. `nik-rev`
+    pub login: String,
+    /// Unique ID of the user
+    pub id: u64,
+}
+
+impl User {
+    /// The email GitHub uses for commits of the user who keeps their email private
+    pub fn noreply_email(&self) -> String {
+        format!("{}+{}@users.noreply.github.com", self.id, self.login)
+    }
+}
+
+/// Base branch of a pull request (returned by github api)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Base {
+    /// Repository that the pull request would be merged into
+    pub repo: Repo,
+    /// Name of the branch, e.g. `master`
+    pub r#ref: BranchName,
+    /// Latest commit of the branch
+    pub sha: CommitId,
 }
 
 /// Head repository (returned by github api)
@@ -30,12 +100,25 @@ pub struct Head {
     pub repo: Repo,
     /// Name of the branch of the PR
     pub r#ref: BranchName,
+    /// Latest commit of the PR
+    pub sha: CommitId,
 }
 
 impl PrData {
+    /// State of the pull request: `open`, `draft`, `merged` or `closed`
+    pub fn state(&self) -> &str {
+        if self.merged {
+            "merged"
+        } else if self.draft && self.state == "open" {
+            "draft"
+        } else {
+            &self.state
+        }
+    }
+
     /// The endpoint which returns the structure `GitHubResponse`
     fn endpoint(repo: &str, pull_request: PrNumber) -> String {
-        format!("https://api.github.com/repos/{repo}/pulls/{pull_request}")
+        format!("{}/repos/{repo}/pulls/{pull_request}", api_url())
     }
 }
 
@@ -44,12 +127,72 @@ impl PrData {
 pub struct Repo {
     /// e.g. `https://github.com/helix-editor/helix.git`
     pub clone_url: String,
+    /// e.g. `master`
+    pub default_branch: BranchName,
 }
 
 impl Repo {
     /// the endpoint that returns the structure [`Repo`]
     pub fn endpoint(owner: &RepoOwner, repo: &RepoName) -> String {
-        format!("https://api.github.com/repos/{owner}/{repo}",)
+        format!("{}/repos/{owner}/{repo}", api_url())
+    }
+}
+
+/// Data returned by GitHub's API for a single commit
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Commit {
+    /// Hash of the commit
+    pub sha: CommitId,
+}
+
+impl Commit {
+    /// The endpoint that returns the structure [`Commit`] for the commit `object` points to
+    pub fn endpoint(owner: &RepoOwner, repo: &RepoName, object: &str) -> String {
+        format!("{}/repos/{owner}/{repo}/commits/{object}", api_url())
+    }
+}
+
+/// Data returned by GitHub's API for the issue search endpoint
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResults {
+    /// Number of issues and pull requests which matched the search, including
+    /// the ones past [`SearchResults::MAX_RESULTS`]
+    pub total_count: usize,
+    /// Issues and pull requests which matched the search
+    pub items: Vec<SearchItem>,
+}
+
+/// A single result of the issue search
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchItem {
+    /// Number of the pull request
+    pub number: PrNumber,
+    /// Title of the pull request
+    pub title: String,
+    /// Url to the pull request
+    pub html_url: String,
+}
+
+impl SearchResults {
+    /// Number of results to request per page
+    const PER_PAGE: usize = 100;
+
+    /// GitHub's search returns at most this many results, later pages fail with `422`
+    const MAX_RESULTS: usize = 1000;
+
+    /// The endpoint that returns the `page` of [`SearchResults`] for pull requests
+    /// in `repo` matching the `query`
+    fn endpoint(repo: &str, query: &str, page: usize) -> Result<String> {
+        reqwest::Url::parse_with_params(
+            &format!("{}/search/issues", api_url()),
+            [
+                ("q", format!("repo:{repo} is:pr {query}")),
+                ("per_page", Self::PER_PAGE.to_string()),
+                ("page", page.to_string()),
+            ],
+        )
+        .map(String::from)
+        .map_err(|err| anyhow!("invalid search query `{query}`: {err}"))
     }
 }
 
@@ -99,7 +242,7 @@ async fn get_gh_api<T: DeserializeOwned>(url: &str, use_gh_cli: bool) -> Result<
             .pipe(String::from_utf8)?
             .pipe(Ok)
     } else {
-        make_request(url).await
+        make_request(url, token().as_deref()).await
     }
     .map(|response| {
         serde_json::from_str::<T>(&response).map_err(|err| {
@@ -108,7 +251,114 @@ async fn get_gh_api<T: DeserializeOwned>(url: &str, use_gh_cli: bool) -> Result<
     })
 }
 
+/// Fetch information about the `pull_request` of `repo` without fetching its branch
+pub async fn fetch_pr_data(repo: &str, pull_request: PrNumber, use_gh_cli: bool) -> Result<PrData> {
+    let url = PrData::endpoint(repo, pull_request);
+
+    get_gh_api::<PrData>(&url, use_gh_cli)
+        .await
+        .map_err(|err| anyhow!("failed to fetch pull request #{pull_request}\n{err}\n"))?
+}
+
+/// Find all pull requests of `repo` which match the GitHub search `query`
+pub async fn search_pull_requests(
+    repo: &str,
+    query: &str,
+    use_gh_cli: bool,
+) -> Result<Vec<SearchItem>> {
+    let mut pull_requests = Vec::new();
+    let mut total_count = 0;
+
+    for page in 1..=SearchResults::MAX_RESULTS / SearchResults::PER_PAGE {
+        let url = SearchResults::endpoint(repo, query, page)?;
+
+        let results = get_gh_api::<SearchResults>(&url, use_gh_cli)
+            .await
+            .map_err(|err| anyhow!("failed to search for pull requests `{query}`:\n{err}\n"))??;
+
+        total_count = results.total_count;
+        let is_last_page = results.items.len() < SearchResults::PER_PAGE;
+        pull_requests.extend(results.items);
+
+        if is_last_page || pull_requests.len() >= total_count {
+            break;
+        }
+    }
+
+    if total_count > pull_requests.len() {
+        log::warn!(
+            "search `{query}` matches {total_count} pull requests, \
+             but GitHub only returns the first {}",
+            pull_requests.len()
+        );
+    }
+
+    Ok(pull_requests)
+}
+
+/// Get the latest commit of the branch of `remote`
+pub async fn fetch_branch_head(
+    remote: &crate::config::Remote,
+    use_gh_cli: bool,
+) -> Result<CommitId> {
+    // `HEAD` is the default branch of the repository
+    let url = Commit::endpoint(
+        &remote.owner,
+        &remote.repo,
+        remote.branch.as_ref().map_or("HEAD", AsRef::as_ref),
+    );
+
+    get_gh_api::<Commit>(&url, use_gh_cli)
+        .await
+        .map_err(|err| anyhow!("failed to fetch branch `{remote}`:\n{err}\n"))?
+        .map(|commit| commit.sha)
+}
+
+/// Get the full hash of the commit `object` of the GitHub `repo`, e.g. of an abbreviated hash
+pub async fn fetch_full_commit(repo: &str, object: &str, use_gh_cli: bool) -> Result<CommitId> {
+    let (owner, name) = repo
+        .split_once('/')
+        .ok_or_else(|| anyhow!("invalid repository `{repo}`, expected `owner/repo`"))?;
+    let url = Commit::endpoint(
+        &RepoOwner::try_new(owner)?,
+        &RepoName::try_new(name)?,
+        object,
+    );
+
+    get_gh_api::<Commit>(&url, use_gh_cli)
+        .await
+        .map_err(|err| anyhow!("failed to fetch commit {object} of {repo}:\n{err}\n"))?
+        .map(|commit| commit.sha)
+}
+
+/// Get the default branch of the GitHub `repo`, e.g. `helix-editor/helix`
+///
+/// Falls back to asking git if the API can't be used
+pub async fn fetch_default_branch(repo: &str, use_gh_cli: bool) -> Result<BranchName> {
+    let from_api = async {
+        let (owner, name) = repo
+            .split_once('/')
+            .ok_or_else(|| anyhow!("invalid repository `{repo}`, expected `owner/repo`"))?;
+        let url = Repo::endpoint(&RepoOwner::try_new(owner)?, &RepoName::try_new(name)?);
+
+        get_gh_api::<Repo>(&url, use_gh_cli)
+            .await?
+            .map(|response| response.default_branch)
+    };
+
+    match from_api.await {
+        Ok(branch) => Ok(branch),
+        Err(err) => {
+            log::debug!("failed to fetch the default branch of {repo} from the API: {err}");
+            git::default_branch(&format!("{}.git", repo_url(repo)))
+                .map_err(|err| anyhow!("failed to find the default branch of {repo}:\n{err}"))
+        }
+    }
+}
+
 /// Fetch the branch of `remote` at the given `commit`
+///
+/// If `remote` has no branch, the default branch of the repository is fetched
 pub async fn fetch_branch(
     remote: &crate::config::Remote,
     use_gh_cli: bool,
@@ -121,14 +371,19 @@ pub async fn fetch_branch(
         .await
         .map_err(|err| anyhow!("failed to fetch branch `{owner}/{repo}`:\n{err}\n"))??;
 
+    let branch = remote
+        .branch
+        .clone()
+        .unwrap_or_else(|| response.default_branch.clone());
+
     let info = RemoteBranch {
         remote: Remote {
             repository_url: response.clone_url.clone(),
             local_remote_alias: with_uuid(&format!("{}/{}", &owner, repo)),
         },
         branch: Branch {
-            local_branch_name: remote.branch.clone(),
-            upstream_branch_name: remote.branch.clone(),
+            local_branch_name: branch.clone(),
+            upstream_branch_name: branch,
         },
     };
 
@@ -143,6 +398,98 @@ pub async fn fetch_branch(
     Ok((response, info))
 }
 
+/// Fetch the branch of `remote`, from GitHub or from any git repository
+pub async fn fetch_branch_remote(
+    remote: &crate::config::BranchRemote,
+    use_gh_cli: bool,
+) -> Result<RemoteBranch> {
+    match remote {
+        crate::config::BranchRemote::GitHub(remote) => {
+            fetch_branch(remote, use_gh_cli).await.map(|(_, info)| info)
+        }
+        crate::config::BranchRemote::Url(url) => fetch_git_url(url),
+    }
+}
+
+/// Get the latest commit of the branch of `remote`, from GitHub or from any git repository
+pub async fn fetch_branch_remote_head(
+    remote: &crate::config::BranchRemote,
+    use_gh_cli: bool,
+) -> Result<CommitId> {
+    match remote {
+        crate::config::BranchRemote::GitHub(remote) => fetch_branch_head(remote, use_gh_cli).await,
+        crate::config::BranchRemote::Url(url) => {
+            let output = git::ls_remote(&url.url, url.upstream_branch().as_ref())
+                .map_err(|err| anyhow!("failed to fetch branch `{url}`:\n{err}\n"))?;
+
+            output
+                .split_whitespace()
+                .next()
+                .ok_or_else(|| anyhow!("branch `{url}` does not exist"))?
+                .pipe(CommitId::try_new)
+                .map_err(|err| anyhow!("invalid commit of branch `{url}`: {err}"))
+        }
+    }
+}
+
+/// Find the default branch of the repository of `remote`, from GitHub or from any git repository
+pub async fn fetch_branch_remote_default_branch(
+    remote: &crate::config::BranchRemote,
+    use_gh_cli: bool,
+) -> Result<BranchName> {
+    match remote {
+        crate::config::BranchRemote::GitHub(remote) => {
+            fetch_default_branch(&format!("{}/{}", remote.owner, remote.repo), use_gh_cli).await
+        }
+        crate::config::BranchRemote::Url(url) => git::default_branch(&url.url),
+    }
+}
+
+/// Fetch the branch of a git repository which is not necessarily on GitHub
+///
+/// If `url` has no branch, the default branch of the repository is fetched
+pub fn fetch_git_url(url: &crate::config::GitUrl) -> Result<RemoteBranch> {
+    let repo_name = url.repo_name();
+
+    let upstream_branch_name = match &url.branch {
+        Some(branch) => branch.clone(),
+        None => git::default_branch(&url.url)
+            .inspect_err(|err| {
+                log::debug!("failed to find the default branch of {url}, using HEAD: {err}");
+            })
+            .unwrap_or_else(|_| url.upstream_branch()),
+    };
+
+    let branch_name = if url.branch.is_some() {
+        upstream_branch_name.to_string()
+    } else {
+        repo_name.to_string()
+    };
+
+    let local_branch_name = match find_first_available_branch(&branch_name) {
+        AvailableBranch::First => {
+            BranchName::try_new(branch_name).map_err(|err| anyhow!("invalid branch name: {err}"))?
+        }
+        AvailableBranch::Other(branch) => branch,
+    };
+
+    let info = RemoteBranch {
+        remote: Remote {
+            repository_url: url.url.clone(),
+            local_remote_alias: with_uuid(repo_name),
+        },
+        branch: Branch {
+            upstream_branch_name,
+            local_branch_name,
+        },
+    };
+
+    add_remote_branch(&info, url.commit.as_ref())
+        .map_err(|err| anyhow!("Could not add remote branch {url}, skipping.\n{err}"))?;
+
+    Ok(info)
+}
+
 /// Fetch PR `pull_request` at `commit_hash` from `repo` to a local `custom_branch_name`,
 /// the branch name is generated if not supplied
 pub async fn fetch_pull_request(
@@ -152,12 +499,23 @@ pub async fn fetch_pull_request(
     commit_hash: Option<&CommitId>,
     use_gh_cli: bool,
 ) -> Result<(PrData, RemoteBranch)> {
-    let url = PrData::endpoint(repo, pull_request);
+    let response = fetch_pr_data(repo, pull_request, use_gh_cli).await?;
 
-    let response = get_gh_api::<PrData>(&url, use_gh_cli)
-        .await
-        .map_err(|err| anyhow!("failed to fetch pull request #{pull_request}\n{err}\n"))??;
+    let remote_branch =
+        fetch_pull_request_branch(&response, pull_request, custom_branch_name, commit_hash)?;
+
+    Ok((response, remote_branch))
+}
 
+/// Fetch the branch of the pull request `pull_request`, whose data was already fetched as
+/// `response`, at `commit_hash` to a local `custom_branch_name`,
+/// the branch name is generated if not supplied
+pub fn fetch_pull_request_branch(
+    response: &PrData,
+    pull_request: PrNumber,
+    custom_branch_name: Option<BranchName>,
+    commit_hash: Option<&CommitId>,
+) -> Result<RemoteBranch> {
     let remote_branch = RemoteBranch {
         remote: Remote {
             repository_url: response.head.repo.clone_url.clone(),
@@ -188,7 +546,7 @@ pub async fn fetch_pull_request(
         anyhow!("failed to add remote branch for pull request #{pull_request}, skipping.\n{err}")
     })?;
 
-    Ok((response, remote_branch))
+    Ok(remote_branch)
 }
 
 /// Available branch name to use
@@ -254,7 +612,7 @@ pub fn add_remote_branch(remote_branch: &RemoteBranch, commit: Option<&CommitId>
         &remote_branch.remote.repository_url,
     ) {
         bail!(
-            "Failed to find branch {} of GitHub repository {}. Are you sure it exists?\n{err}",
+            "Failed to find branch {} of repository {}. Are you sure it exists?\n{err}",
             remote_branch.branch.upstream_branch_name,
             remote_branch.remote.repository_url
         );
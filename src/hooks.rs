@@ -0,0 +1,115 @@
+//! Commands from `[hooks]` of the config, which check patchy's branch while it is built
+
+use std::fs;
+use std::process::Command;
+
+use anyhow::{Result, anyhow, bail};
+use colored::Colorize as _;
+
+use crate::config::HookPolicy;
+use crate::git;
+use crate::lock::{HookResult, Outcome};
+
+/// Run each of the `commands` in the root of the repository, until one of them fails
+///
+/// The `entry` that was just merged, if any, is passed in the `PATCHY_ENTRY` environment variable.
+/// Changes the commands make to tracked files are discarded, and untracked files which they
+/// create are removed, so they don't get in the way of the next merge. Ignored files,
+/// e.g. `target/`, are kept
+pub fn run(commands: &[String], entry: Option<&str>) -> Result<()> {
+    let untracked = git::untracked_files()?;
+    let result = run_commands(commands, entry);
+
+    git::reset_hard("HEAD")?;
+
+    for file in git::untracked_files()? {
+        if !untracked.contains(&file) {
+            log::debug!("Removing {file}, which a hook created");
+            fs::remove_file(git::ROOT.join(&file))
+                .map_err(|err| anyhow!("failed to remove {file}, which a hook created: {err}"))?;
+        }
+    }
+
+    result
+}
+
+/// Run each of the `commands` in the root of the repository, until one of them fails
+fn run_commands(commands: &[String], entry: Option<&str>) -> Result<()> {
+    for command in commands {
+        log::info!("Running hook {}", command.bright_cyan());
+
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+
+        if let Some(entry) = entry {
+            shell.env("PATCHY_ENTRY", entry);
+        }
+
+        let status = shell
+            .arg(command)
+            .current_dir(&*git::ROOT)
+            .status()
+            .map_err(|err| anyhow!("failed to run hook `{command}`: {err}"))?;
+
+        if !status.success() {
+            bail!("hook `{command}` failed with {status}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the `after-entry` `commands` for the `entry`, which was merged on top of the commit `before`
+///
+/// If they fail, the `policy` decides whether to stop the build, carry on,
+/// or drop the entry by resetting patchy's branch to `before`
+///
+/// Returns the outcome of the entry, and the result of the hooks
+pub fn check_entry(
+    commands: &[String],
+    policy: HookPolicy,
+    entry: &str,
+    before: &str,
+) -> Result<(Outcome, Option<HookResult>)> {
+    if commands.is_empty() {
+        return Ok((Outcome::Merged, None));
+    }
+
+    let Err(err) = run(commands, Some(entry)) else {
+        return Ok((Outcome::Merged, Some(HookResult::Passed)));
+    };
+
+    match policy {
+        HookPolicy::Fail => bail!("{err}\nStopping, because the hooks fail after {entry}"),
+        HookPolicy::Warn => {
+            log::warn!("{err}\nKeeping {entry} anyway");
+            Ok((Outcome::Merged, Some(HookResult::Failed)))
+        }
+        HookPolicy::Drop => {
+            log::warn!("{err}\nDropping {entry}");
+            git::reset_hard(before)?;
+            Ok((Outcome::Dropped, Some(HookResult::Failed)))
+        }
+    }
+}
+
+/// Run the `commands` which check the whole branch, e.g. `after-base` and `after-all`
+///
+/// Nothing can be dropped, so the `drop` policy only warns
+pub fn check_branch(commands: &[String], policy: HookPolicy) -> Result<()> {
+    match run(commands, None) {
+        Ok(()) => Ok(()),
+        Err(err) if policy == HookPolicy::Fail => Err(err),
+        Err(err) => {
+            log::warn!("{err}");
+            Ok(())
+        }
+    }
+}
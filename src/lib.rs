@@ -7,6 +7,11 @@ mod commands;
 mod config;
 mod git;
 mod github;
+mod hooks;
+mod lock;
+mod patch;
+mod remote_url;
+mod select;
 mod utils;
 
 pub use cli::Cli;
@@ -0,0 +1,252 @@
+//! Record of what `patchy run` merged
+//!
+//! The lockfile is written to patchy's config directory, so it is committed to
+//! patchy's branch together with the config, keeping the result of each run auditable.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, CommitId, PrNumber};
+use crate::git;
+
+/// Name of the lockfile in patchy's config directory
+pub const FILE: &str = "patchy.lock";
+
+/// Absolute path to the lockfile
+pub fn path() -> PathBuf {
+    config::PATH.join(FILE)
+}
+
+/// Everything that went into patchy's branch during a run
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Lockfile {
+    /// The branch that everything was merged into
+    pub base: Base,
+    /// Queries which were expanded into pull requests
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queries: Vec<Query>,
+    /// Pull requests, in the order they were merged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pull_requests: Vec<PullRequest>,
+    /// Branches, in the order they were merged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<Branch>,
+    /// Patches, in the order they were applied
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<Patch>,
+}
+
+impl Lockfile {
+    /// Write the lockfile to patchy's config directory
+    pub fn write(&self) -> Result<()> {
+        let contents =
+            toml::to_string(self).map_err(|err| anyhow!("failed to serialize {FILE}: {err}"))?;
+
+        fs::write(
+            path(),
+            format!("# Generated by patchy. Do not edit.\n\n{contents}"),
+        )
+        .map_err(|err| anyhow!("failed to write {FILE}: {err}"))
+    }
+
+    /// Read the lockfile which `patchy run` committed to the `branch`
+    ///
+    /// Returns `None` if the branch has no lockfile
+    pub fn read_from(branch: &str) -> Result<Option<Self>> {
+        let Ok(contents) = git::show_file(branch, &format!("{}/{FILE}", config::ROOT.as_str()))
+        else {
+            return Ok(None);
+        };
+
+        toml::from_str(&contents)
+            .map(Some)
+            .map_err(|err| anyhow!("failed to parse {FILE} of {branch}: {err}"))
+    }
+
+    /// Names of all entries which could not be merged
+    pub fn failed(&self) -> Vec<String> {
+        let pull_requests = self
+            .pull_requests
+            .iter()
+            .filter(|pull_request| {
+                matches!(
+                    pull_request.outcome,
+                    Outcome::Failed | Outcome::Skipped | Outcome::Dropped
+                )
+            })
+            .map(|pull_request| format!("#{}", pull_request.number));
+        let branches = self
+            .branches
+            .iter()
+            .filter(|branch| matches!(branch.outcome, Outcome::Failed | Outcome::Dropped))
+            .map(|branch| branch.remote.clone());
+        let patches = self
+            .patches
+            .iter()
+            .filter(|patch| matches!(patch.outcome, Outcome::Failed | Outcome::Dropped))
+            .map(|patch| patch.name.clone());
+
+        pull_requests.chain(branches).chain(patches).collect()
+    }
+}
+
+/// The branch that everything was merged into
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Base {
+    /// e.g. `helix-editor/helix`
+    pub repo: String,
+    /// e.g. `master`
+    pub branch: String,
+    /// The commit of `branch` which was used
+    pub commit: Option<CommitId>,
+}
+
+/// A query from `pull-request-queries`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Query {
+    /// The search query
+    pub query: String,
+    /// Pull requests that the query matched
+    pub pull_requests: Vec<PrNumber>,
+}
+
+/// What happened to an entry during the run
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The entry is on patchy's branch
+    Merged,
+    /// The entry could not be merged
+    Failed,
+    /// The entry is disabled in the config, or depends on a disabled pull request
+    Disabled,
+    /// The entry was not merged, because of an earlier failure
+    Skipped,
+    /// The entry was merged, and then removed because the hooks failed
+    Dropped,
+}
+
+impl Outcome {
+    /// The outcome as it is written in the lockfile
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Merged => "merged",
+            Self::Failed => "failed",
+            Self::Disabled => "disabled",
+            Self::Skipped => "skipped",
+            Self::Dropped => "dropped",
+        }
+    }
+}
+
+/// Result of the `after-entry` hooks for an entry
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookResult {
+    /// All hooks succeeded
+    Passed,
+    /// A hook failed
+    Failed,
+}
+
+/// A pull request that was merged
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PullRequest {
+    /// Number of the pull request
+    pub number: PrNumber,
+    /// Title of the pull request
+    pub title: Option<String>,
+    /// Link to the pull request
+    pub url: Option<String>,
+    /// GitHub user who opened the pull request
+    pub author: Option<String>,
+    /// Commit of the pull request which was merged
+    pub head: Option<CommitId>,
+    /// If the pull request comes from `pull-request-queries`, the query that matched it
+    pub query: Option<String>,
+    /// What happened to the pull request
+    pub outcome: Outcome,
+    /// Why the pull request was not merged
+    pub reason: Option<String>,
+    /// Result of the `after-entry` hooks
+    pub hooks: Option<HookResult>,
+}
+
+/// A branch that was merged
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Branch {
+    /// e.g. `helix-editor/helix/master`
+    pub remote: String,
+    /// Commit of the branch which was merged, if it was pinned
+    pub commit: Option<CommitId>,
+    /// Subject of the latest commit of the branch
+    pub subject: Option<String>,
+    /// What happened to the branch
+    pub outcome: Outcome,
+    /// Result of the `after-entry` hooks
+    pub hooks: Option<HookResult>,
+}
+
+/// A patch that was applied
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Patch {
+    /// Name of the patch
+    pub name: String,
+    /// Subject of the commit that the patch became
+    pub subject: Option<String>,
+    /// What happened to the patch
+    pub outcome: Outcome,
+    /// Result of the `after-entry` hooks
+    pub hooks: Option<HookResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let lockfile = Lockfile {
+            base: Base {
+                repo: "helix-editor/helix".to_string(),
+                branch: "master".to_string(),
+                commit: Some("a1b2c3".try_into().unwrap()),
+            },
+            queries: vec![Query {
+                query: "label:ready-for-review".to_string(),
+                pull_requests: vec![12254.try_into().unwrap()],
+            }],
+            pull_requests: vec![PullRequest {
+                number: 12254.try_into().unwrap(),
+                title: Some("Add file explorer".to_string()),
+                url: Some("https://github.com/helix-editor/helix/pull/12254".to_string()),
+                author: Some("nik-rev".to_string()),
+                head: Some("deadbeef".try_into().unwrap()),
+                query: Some("label:ready-for-review".to_string()),
+                outcome: Outcome::Merged,
+                reason: None,
+                hooks: Some(HookResult::Passed),
+            }],
+            branches: vec![],
+            patches: vec![Patch {
+                name: "remove-tab".to_string(),
+                subject: None,
+                outcome: Outcome::Failed,
+                hooks: None,
+            }],
+        };
+
+        let serialized = toml::to_string(&lockfile).unwrap();
+
+        pretty_assertions::assert_eq!(toml::from_str::<Lockfile>(&serialized).unwrap(), lockfile);
+    }
+}
@@ -0,0 +1,221 @@
+//! Files of the `patches` in patchy's config directory
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow, bail};
+use colored::Colorize as _;
+use itertools::Itertools as _;
+use sha2::{Digest as _, Sha256};
+
+use crate::config::{self, Download, Patch, PatchName};
+use crate::git::{self, Applied};
+use crate::utils::make_bytes_request;
+
+/// Extensions of the files that can be patches
+pub const EXTENSIONS: [&str; 3] = ["patch", "diff", "mbox"];
+
+/// Files of the patch `name`, which is one of:
+///
+/// - A file with one of the [`EXTENSIONS`], e.g. `fix.diff`
+/// - The name of such a file without its extension, e.g. `fix`
+/// - A directory with a series of patches, which are applied in order of their file names
+///
+/// Returns `None` if none of these exist
+pub fn files(name: &PatchName) -> Result<Option<Vec<PathBuf>>> {
+    let dir = git::ROOT.join(config::ROOT.as_str());
+    let path = dir.join(name.as_ref());
+
+    if path.is_file() && has_patch_extension(&path) {
+        return Ok(Some(vec![path]));
+    }
+
+    if let Some(file) = EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{name}.{extension}")))
+        .find(|file| file.is_file())
+    {
+        return Ok(Some(vec![file]));
+    }
+
+    if !path.is_dir() {
+        return Ok(None);
+    }
+
+    let mut files = fs::read_dir(&path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .filter_ok(|file| has_patch_extension(file))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if files.is_empty() {
+        bail!("series of patches {} is empty", path.display());
+    }
+
+    files.sort();
+
+    Ok(Some(files))
+}
+
+/// Download the `patch` from its URL into patchy's config directory, so [`files`] finds it
+///
+/// The downloaded file is kept, and only downloaded again if its checksum no longer matches
+pub async fn download(patch: &Patch, download: &Download) -> Result<()> {
+    let file = file_of(&patch.name);
+
+    if fs::read(&file).is_ok_and(|contents| sha256(&contents) == download.sha256) {
+        log::debug!("Using cached {} for patch {patch}", file.display());
+        return Ok(());
+    }
+
+    log::info!(
+        "Downloading patch {patch} from {}",
+        download.url.bright_blue()
+    );
+
+    let contents = make_bytes_request(&download.url)
+        .await
+        .map_err(|err| anyhow!("failed to download patch {patch}:\n{err}"))?;
+
+    let checksum = sha256(&contents);
+    if checksum != download.sha256 {
+        bail!(
+            "checksum of patch {patch} does not match, it might have been changed at {}\n  \
+             expected sha256: {}\n  \
+             actual sha256:   {checksum}\n\
+             If the new patch is trusted, update `sha256` of the patch in your config",
+            download.url,
+            download.sha256
+        );
+    }
+
+    fs::write(&file, contents)?;
+
+    Ok(())
+}
+
+/// File that the patch `name` is downloaded or generated to
+///
+/// Names without one of the [`EXTENSIONS`] get the `.patch` extension
+pub fn file_of(name: &PatchName) -> PathBuf {
+    let path = config::PATH.join(name.as_ref());
+
+    if has_patch_extension(&path) {
+        path
+    } else {
+        config::PATH.join(format!("{name}.patch"))
+    }
+}
+
+/// SHA-256 checksum of the `contents`, as lowercase hex
+fn sha256(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+/// `true` if the `file` has one of the [`EXTENSIONS`]
+fn has_patch_extension(file: &Path) -> bool {
+    file.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension))
+}
+
+/// Format of a file of a patch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Output of `git format-patch`, or any other mailbox, which contains its commit message
+    Mailbox,
+    /// Output of `git diff` or `diff -u`, without a commit message
+    Diff,
+}
+
+impl Format {
+    /// Format of the `file`
+    ///
+    /// `.diff` files are always plain diffs. Other files are mailboxes if they start
+    /// with a `From` header, like the output of `git format-patch`
+    pub fn of(file: &Path) -> Result<Self> {
+        if file
+            .extension()
+            .is_some_and(|extension| extension == "diff")
+        {
+            return Ok(Self::Diff);
+        }
+
+        Ok(Self::detect(&fs::read_to_string(file)?))
+    }
+
+    /// Format of a file with the `contents`
+    fn detect(contents: &str) -> Self {
+        if contents.starts_with("From ") || contents.starts_with("From:") {
+            Self::Mailbox
+        } else {
+            Self::Diff
+        }
+    }
+
+    /// Apply the `file` of the `patch` to the current branch
+    pub fn apply(self, file: &Path, patch: &Patch) -> Result<Applied> {
+        match self {
+            Self::Mailbox => git::apply_patch(file),
+            Self::Diff => git::apply_diff(file, &patch.message(), patch.author.as_deref()),
+        }
+    }
+
+    /// Discard the conflict that [`Format::apply`] left behind
+    pub fn abort(self) -> Result<()> {
+        match self {
+            Self::Mailbox => git::abort_am().map(drop),
+            Self::Diff => git::reset_hard("HEAD").map(drop),
+        }
+    }
+
+    /// Commit the user's resolution of the conflict that [`Format::apply`] left behind
+    pub fn resume(self, patch: &Patch) -> Result<()> {
+        match self {
+            // the user might have already run `git am --continue`
+            Self::Mailbox if git::is_am_in_progress() => git::continue_am().map(drop),
+            // the user might have already committed
+            Self::Diff if git::is_worktree_dirty() => {
+                git::commit_as(&patch.message(), patch.author.as_deref()).map(drop)
+            }
+            Self::Mailbox | Self::Diff => Ok(()),
+        }
+    }
+
+    /// Contents of a file in this format with the commits after `commit`
+    pub fn regenerate(self, commit: &str) -> Result<String> {
+        match self {
+            Self::Mailbox => git::format_patches_since(commit),
+            Self::Diff => git::diff_since(commit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(
+            sha256(b"test"),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(
+            Format::detect("From 1a2b3c Mon Sep 17 00:00:00 2001\nFrom: Someone\n"),
+            Format::Mailbox
+        );
+        assert_eq!(
+            Format::detect("From: Someone <someone@example.com>\nSubject: Fix\n"),
+            Format::Mailbox
+        );
+        assert_eq!(
+            Format::detect("diff --git a/f b/f\nindex 1..2 100644\n"),
+            Format::Diff
+        );
+        assert_eq!(Format::detect("--- a/f\n+++ b/f\n"), Format::Diff);
+    }
+}
@@ -0,0 +1,227 @@
+//! Choose which entries of the config to use for a run
+
+use std::mem;
+
+use anyhow::{Result, anyhow};
+use colored::Colorize as _;
+use futures::future::join_all;
+
+use crate::config::{self, Config, edit::ConfigFile};
+use crate::lock::{Lockfile, Outcome};
+use crate::{confirm_prompt, github};
+
+/// An entry that can be selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    /// Index into `pull-requests`
+    PullRequest(usize),
+    /// Index into `branches`
+    Branch(usize),
+    /// Index into `patches`
+    Patch(usize),
+}
+
+/// Ask which pull requests, branches and patches of the `config` to use for this run,
+/// and disable the others
+///
+/// Each entry is shown with the title and state of the pull request, and its outcome in the
+/// last run. Afterwards, offers to save the selection to the config file, or to the `profile`
+/// which the `config` was read with
+pub async fn select(config: &mut Config, profile: Option<&str>, use_gh_cli: bool) -> Result<()> {
+    let lockfile = Lockfile::read_from(config.local_branch.as_ref())?.unwrap_or_default();
+
+    let pr_data =
+        join_all(config.pull_requests.iter().map(|pull_request| {
+            github::fetch_pr_data(&config.repo, pull_request.number, use_gh_cli)
+        }))
+        .await;
+
+    let mut items = Vec::new();
+    let mut labels = Vec::new();
+    let mut defaults = Vec::new();
+
+    for (index, (pull_request, response)) in config.pull_requests.iter().zip(pr_data).enumerate() {
+        let last_run = lockfile
+            .pull_requests
+            .iter()
+            .find(|record| record.number == pull_request.number)
+            .map(|record| record.outcome);
+
+        let description = match response {
+            Ok(response) => format!("{} {}", response.title, format_state(response.state())),
+            Err(err) => {
+                log::debug!("{err}");
+                String::new()
+            }
+        };
+
+        items.push(Item::PullRequest(index));
+        labels.push(format!(
+            "#{} {description}{}",
+            pull_request.number.to_string().bright_blue(),
+            format_last_run(last_run)
+        ));
+        defaults.push(pull_request.options.enabled);
+    }
+
+    for (index, branch) in config.branches.iter().enumerate() {
+        let name = branch.remote.name();
+        let last_run = lockfile
+            .branches
+            .iter()
+            .find(|record| record.remote == name)
+            .map(|record| record.outcome);
+
+        items.push(Item::Branch(index));
+        labels.push(format!(
+            "{}{}",
+            branch.remote.to_string().bright_blue(),
+            format_last_run(last_run)
+        ));
+        defaults.push(branch.options.enabled);
+    }
+
+    for (index, patch) in config.patches.iter().enumerate() {
+        let name = patch.to_string();
+        let last_run = lockfile
+            .patches
+            .iter()
+            .find(|record| record.name == name)
+            .map(|record| record.outcome);
+
+        items.push(Item::Patch(index));
+        labels.push(format!(
+            "{} {}{}",
+            "patch".bright_black(),
+            name.bright_blue(),
+            format_last_run(last_run)
+        ));
+        defaults.push(patch.enabled);
+    }
+
+    if items.is_empty() {
+        log::warn!("There are no pull requests, branches or patches in your config");
+        return Ok(());
+    }
+
+    let selection = dialoguer::MultiSelect::new()
+        .with_prompt(format!(
+            "\n  {} Choose the entries to merge, with {} to toggle and {} to confirm",
+            "»".bright_black(),
+            "space".bright_cyan(),
+            "enter".bright_cyan()
+        ))
+        .items(&labels)
+        .defaults(&defaults)
+        .interact()
+        .map_err(|err| anyhow!("failed to read the selection: {err}"))?;
+
+    let changed = items
+        .iter()
+        .zip(defaults)
+        .enumerate()
+        .filter(|(index, (_, enabled))| selection.contains(index) != *enabled)
+        .map(|(index, (item, _))| (*item, selection.contains(&index)))
+        .collect::<Vec<_>>();
+
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let mut patches = mem::take(&mut config.patches)
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    for &(item, enabled) in &changed {
+        match item {
+            Item::PullRequest(index) => {
+                if let Some(pull_request) = config.pull_requests.get_mut(index) {
+                    pull_request.options.enabled = enabled;
+                }
+            }
+            Item::Branch(index) => {
+                if let Some(branch) = config.branches.get_mut(index) {
+                    branch.options.enabled = enabled;
+                }
+            }
+            Item::Patch(index) => {
+                if let Some(patch) = patches.get_mut(index) {
+                    patch.enabled = enabled;
+                }
+            }
+        }
+    }
+
+    config.patches = patches.into_iter().collect();
+
+    if confirm_prompt!("Save this selection to {}?", config::FILE.bright_cyan()) {
+        save(config, profile, &changed)?;
+    }
+
+    Ok(())
+}
+
+/// Write whether each of the `changed` entries of the `config` is enabled to the config file
+///
+/// Entries which the `profile` overrides are changed in the profile
+fn save(config: &Config, profile: Option<&str>, changed: &[(Item, bool)]) -> Result<()> {
+    let mut file = ConfigFile::open()?;
+
+    for &(item, enabled) in changed {
+        let result = match item {
+            Item::PullRequest(index) => config.pull_requests.get(index).map(|pull_request| {
+                file.set_pull_request_enabled(profile, pull_request.number, enabled)
+            }),
+            Item::Branch(index) => config
+                .branches
+                .get(index)
+                .map(|branch| file.set_branch_enabled(profile, &branch.remote, enabled)),
+            Item::Patch(index) => config
+                .patches
+                .get_index(index)
+                .map(|patch| file.set_patch_enabled(profile, &patch.name, enabled)),
+        };
+
+        // entries of included files are not in the lists of the config file
+        if let Some(Err(err)) = result {
+            log::warn!("{err}, so it is only changed for this run");
+        }
+    }
+
+    file.save()?;
+
+    log::info!("Saved the selection to {}", config::FILE.bright_cyan());
+
+    Ok(())
+}
+
+/// Format the state of a pull request, e.g. `open`
+fn format_state(state: &str) -> String {
+    let text = format!("[{state}]");
+
+    match state {
+        "open" => text.bright_green(),
+        "draft" => text.bright_black(),
+        "merged" => text.bright_magenta(),
+        _ => text.bright_red(),
+    }
+    .to_string()
+}
+
+/// Format the outcome of an entry in the last run, if it was in the last run
+fn format_last_run(outcome: Option<Outcome>) -> String {
+    let Some(outcome) = outcome else {
+        return String::new();
+    };
+
+    let text = format!("(last run: {})", outcome.as_str());
+
+    format!(
+        " {}",
+        match outcome {
+            Outcome::Merged => text.bright_black(),
+            Outcome::Disabled | Outcome::Skipped => text.yellow(),
+            Outcome::Failed | Outcome::Dropped => text.bright_red(),
+        }
+    )
+}
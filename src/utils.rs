@@ -5,7 +5,7 @@ use std::{fmt::Display, sync::LazyLock};
 use anyhow::anyhow;
 use colored::Colorize as _;
 use rand::{Rng as _, distributions};
-use reqwest::{Client, header::USER_AGENT};
+use reqwest::{Client, Response, header::USER_AGENT};
 use tap::Pipe as _;
 
 use crate::config::PrNumber;
@@ -52,20 +52,54 @@ pub fn format_pr(pr: PrNumber, pr_title: &str, url: &str) -> String {
     )
 }
 
+/// Format the note of a config entry for display after the entry, if it has one
+pub fn format_note(note: Option<&str>) -> String {
+    note.map(|note| format!(" {}", format!("({note})").bright_black()))
+        .unwrap_or_default()
+}
+
 /// Style a snippet of text as a link
 pub fn format_url(text: impl Display, url: impl Display) -> String {
     format!("\u{1b}]8;;{url}\u{1b}\\{text}\u{1b}]8;;\u{1b}\\")
 }
 
-/// Send a GET request to the specified URL
+/// Abbreviated hash of the `commit`
+pub fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
+
+/// Send a GET request to the specified URL, authenticated with the bearer `token` if there is one
 ///
 /// Return the result as text
-pub async fn make_request(url: &str) -> anyhow::Result<String> {
+pub async fn make_request(url: &str, token: Option<&str>) -> anyhow::Result<String> {
+    send_request(url, token).await?.text().await?.pipe(Ok)
+}
+
+/// Send a GET request to the specified URL
+///
+/// Return the result as it is, e.g. to check the checksum of a downloaded file
+pub async fn make_bytes_request(url: &str) -> anyhow::Result<Vec<u8>> {
+    send_request(url, None)
+        .await?
+        .bytes()
+        .await?
+        .to_vec()
+        .pipe(Ok)
+}
+
+/// Send a GET request to the specified URL, authenticated with the bearer `token` if there is one
+///
+/// Fails if the response is not successful
+async fn send_request(url: &str, token: Option<&str>) -> anyhow::Result<Response> {
     static CLIENT: LazyLock<Client> = LazyLock::new(Client::new);
-    let request = CLIENT.get(url).header(USER_AGENT, "patchy").send().await;
+    let mut request = CLIENT.get(url).header(USER_AGENT, "patchy");
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let request = request.send().await;
 
     match request {
-        Ok(res) if res.status().is_success() => res.text().await?.pipe(Ok),
+        Ok(res) if res.status().is_success() => Ok(res),
         Ok(res) => {
             let status = res.status();
             let text = res.text().await?;
@@ -0,0 +1,323 @@
+//! `patchy run`, in repositories which only exist on disk
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Read as _, Write as _};
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Output};
+    use std::thread;
+
+    use pretty_assertions::assert_eq;
+    use sha2::{Digest as _, Sha256};
+    use tempfile::TempDir;
+
+    /// Set the identity of commits, which is not configured on every machine
+    fn identity(command: &mut Command) -> &mut Command {
+        command
+            .env("GIT_AUTHOR_NAME", "patchy")
+            .env("GIT_AUTHOR_EMAIL", "patchy@example.com")
+            .env("GIT_COMMITTER_NAME", "patchy")
+            .env("GIT_COMMITTER_EMAIL", "patchy@example.com")
+    }
+
+    /// Run `git` in `dir`, and get its output
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = identity(Command::new("git").args(args).current_dir(dir))
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "git {}\n{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .trim_end()
+            .to_string()
+    }
+
+    /// Commit the `file` in `dir` on a new `branch`, which starts at `main`
+    fn commit_on_branch(dir: &Path, branch: &str, file: &str) {
+        git(dir, &["checkout", "--quiet", "-b", branch, "main"]);
+        fs::write(dir.join(file), file).unwrap();
+        git(dir, &["add", file]);
+        git(dir, &["commit", "--quiet", "--message", file]);
+    }
+
+    /// Serve the `body` over HTTP for every request, and get its URL
+    fn serve(body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/fix.patch", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+
+        url
+    }
+
+    /// A fork of an upstream repository with the branches `broken` and `fine`
+    struct Fork {
+        /// Holds both repositories, and the user config
+        dir: TempDir,
+        /// Root of the fork
+        root: PathBuf,
+        /// Path of the upstream repository
+        upstream: String,
+    }
+
+    impl Fork {
+        /// Create the repositories, with the `config` added to the config of the fork
+        fn new(config: &str) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let upstream = dir.path().join("upstream");
+            let root = dir.path().join("fork");
+
+            fs::create_dir_all(&upstream).unwrap();
+            git(&upstream, &["init", "--quiet", "--initial-branch", "main"]);
+            fs::write(upstream.join("README.md"), "upstream").unwrap();
+            git(&upstream, &["add", "README.md"]);
+            git(&upstream, &["commit", "--quiet", "--message", "initial"]);
+            commit_on_branch(&upstream, "broken", "broken");
+            commit_on_branch(&upstream, "fine", "fine");
+            git(&upstream, &["checkout", "--quiet", "main"]);
+
+            let upstream = upstream.to_str().unwrap().to_string();
+            git(dir.path(), &["clone", "--quiet", &upstream, "fork"]);
+            git(&root, &["remote", "add", "upstream", &upstream]);
+
+            fs::create_dir_all(root.join(".patchy")).unwrap();
+            fs::write(
+                root.join(".patchy/config.toml"),
+                format!(
+                    r#"
+repo = "helix-editor/helix"
+base-remote = "upstream"
+remote-branch = "main"
+local-branch = "patchy"
+branches = ["{upstream}#broken", "{upstream}#fine"]
+{config}"#
+                ),
+            )
+            .unwrap();
+
+            Self {
+                dir,
+                root,
+                upstream,
+            }
+        }
+
+        /// Run `patchy run` in the fork
+        fn run(&self) -> Output {
+            identity(
+                Command::new(env!("CARGO_BIN_EXE_patchy"))
+                    .args(["run", "--confirm", "yes"])
+                    .current_dir(&self.root)
+                    .env("XDG_CONFIG_HOME", self.dir.path().join("config")),
+            )
+            .output()
+            .unwrap()
+        }
+    }
+
+    /// Config with an `after-entry` hook which fails once `broken` is merged, with the `policy`
+    fn failing_hook(policy: &str) -> String {
+        format!(
+            r#"
+[hooks]
+after-entry = ["touch output && test ! -e broken"]
+policy = "{policy}"
+"#
+        )
+    }
+
+    #[test]
+    fn drop_entry_when_hook_fails() {
+        let fork = Fork::new(&failing_hook("drop"));
+        let upstream = &fork.upstream;
+        let base = git(&fork.root, &["rev-parse", "main"]);
+
+        let output = fork.run();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let lockfile = fs::read_to_string(fork.root.join(".patchy/patchy.lock")).unwrap();
+        assert!(
+            lockfile.contains(&format!(
+                "remote = \"{upstream}#broken\"\nsubject = \"broken\"\noutcome = \"dropped\""
+            )),
+            "{lockfile}"
+        );
+        assert!(
+            lockfile.contains(&format!(
+                "remote = \"{upstream}#fine\"\nsubject = \"fine\"\noutcome = \"merged\""
+            )),
+            "{lockfile}"
+        );
+
+        // `broken` was reset away, so `fine` is merged right on top of the base
+        assert_eq!(
+            git(&fork.root, &["log", "--format=%s", "patchy"])
+                .lines()
+                .collect::<Vec<_>>(),
+            [
+                "patchy: restore configuration files",
+                &format!("patchy: Merge branch {upstream}#fine"),
+                "initial"
+            ]
+        );
+        assert_eq!(git(&fork.root, &["rev-parse", "patchy~2"]), base);
+        assert!(fork.root.join("fine").exists());
+        assert!(!fork.root.join("broken").exists());
+
+        assert!(
+            !fork.root.join("output").exists(),
+            "untracked files of hooks are removed"
+        );
+    }
+
+    #[test]
+    fn stop_when_hook_fails() {
+        let fork = Fork::new(&failing_hook("fail"));
+        let head = git(&fork.root, &["rev-parse", "HEAD"]);
+
+        let output = fork.run();
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains(&format!(
+                "Stopping, because the hooks fail after {}#broken",
+                fork.upstream
+            )),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        // `local-branch` was never created, and the fork is back where it was
+        assert_eq!(
+            git(&fork.root, &["branch", "--list", "patchy"]),
+            "",
+            "local-branch is not written"
+        );
+        assert_eq!(git(&fork.root, &["rev-parse", "HEAD"]), head);
+        assert!(!fork.root.join("output").exists());
+    }
+
+    #[test]
+    fn discard_changes_of_hooks_when_warning() {
+        let fork = Fork::new(
+            r#"
+[hooks]
+after-entry = ["echo changed > README.md && test ! -e broken"]
+policy = "warn"
+"#,
+        );
+        let upstream = &fork.upstream;
+
+        let output = fork.run();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let lockfile = fs::read_to_string(fork.root.join(".patchy/patchy.lock")).unwrap();
+        assert!(
+            lockfile.contains(&format!(
+                "remote = \"{upstream}#broken\"\nsubject = \"broken\"\noutcome = \"merged\""
+            )),
+            "{lockfile}"
+        );
+
+        // the hooks changed README.md after each entry, which is not committed
+        assert_eq!(git(&fork.root, &["status", "--porcelain"]), "");
+        assert_eq!(git(&fork.root, &["show", "patchy:README.md"]), "upstream");
+        assert_eq!(
+            fs::read_to_string(fork.root.join("README.md")).unwrap(),
+            "upstream"
+        );
+    }
+
+    #[test]
+    fn rebased_commits_get_trailers() {
+        let fork = Fork::new(r#"merge-strategy = "rebase""#);
+        let upstream = &fork.upstream;
+
+        let output = fork.run();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        // each commit keeps its message, and gets the trailers of its branch
+        assert_eq!(
+            git(
+                &fork.root,
+                &[
+                    "log",
+                    "--format=%s %(trailers:key=Patchy-Branch,valueonly,separator=)",
+                    "patchy~1",
+                    "^main"
+                ]
+            )
+            .lines()
+            .collect::<Vec<_>>(),
+            [
+                format!("fine {upstream}#fine"),
+                format!("broken {upstream}#broken")
+            ]
+        );
+    }
+
+    #[test]
+    fn downloaded_patch_is_not_written_back() {
+        const PATCH: &[u8] = b"diff --git a/downloaded b/downloaded
+new file mode 100644
+index 0000000..e69de29
+--- /dev/null
++++ b/downloaded
+@@ -0,0 +1 @@
++downloaded
+";
+        let url = serve(PATCH);
+        let sha256 = format!("{:x}", Sha256::digest(PATCH));
+
+        let fork = Fork::new(&format!(
+            r#"patches = [{{ name = "fix", url = "{url}", sha256 = "{sha256}" }}]"#
+        ));
+
+        let output = fork.run();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{stderr}");
+        assert!(
+            stderr.contains("Applied patch fix"),
+            "the patch is downloaded and applied\n{stderr}"
+        );
+        assert!(fork.root.join("downloaded").exists());
+
+        // writing back would check out the previous branch, `main`
+        assert!(!stderr.contains("regenerated"), "{stderr}");
+        assert_eq!(
+            git(&fork.root, &["rev-parse", "--abbrev-ref", "HEAD"]),
+            "patchy"
+        );
+    }
+}